@@ -0,0 +1,161 @@
+/* Copyright (c) 2023 Yuki Kishimoto */
+/* Distributed under the MIT software license */
+
+#ifndef NEGENTROPY_H
+#define NEGENTROPY_H
+
+/* Generated with cbindgen: do not edit, run `make header` */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Status code
+typedef enum NegentropyStatus {
+  // Success
+  NEGENTROPY_STATUS_OK = 0,
+  // Null pointer argument
+  NEGENTROPY_STATUS_NULL_POINTER,
+  // ID too big
+  NEGENTROPY_STATUS_ID_TOO_BIG,
+  // Invalid ID size
+  NEGENTROPY_STATUS_INVALID_ID_SIZE,
+  // Frame size limit too small
+  NEGENTROPY_STATUS_FRAME_SIZE_LIMIT_TOO_SMALL,
+  // Not sealed
+  NEGENTROPY_STATUS_NOT_SEALED,
+  // Already sealed
+  NEGENTROPY_STATUS_ALREADY_SEALED,
+  // Already built initial message
+  NEGENTROPY_STATUS_ALREADY_BUILT_INITIAL_MESSAGE,
+  // Initiator error
+  NEGENTROPY_STATUS_INITIATOR,
+  // Non-initiator error
+  NEGENTROPY_STATUS_NON_INITIATOR,
+  // Unexpected mode
+  NEGENTROPY_STATUS_UNEXPECTED_MODE,
+  // Parse ends prematurely
+  NEGENTROPY_STATUS_PARSE_ENDS_PREMATURELY,
+  // Protocol version not found
+  NEGENTROPY_STATUS_PROTOCOL_VERSION_NOT_FOUND,
+  // Invalid protocol version
+  NEGENTROPY_STATUS_INVALID_PROTOCOL_VERSION,
+  // Unsupported protocol version
+  NEGENTROPY_STATUS_UNSUPPORTED_PROTOCOL_VERSION,
+  // Try from slice error
+  NEGENTROPY_STATUS_TRY_FROM_SLICE,
+  // Bad range
+  NEGENTROPY_STATUS_BAD_RANGE,
+  // Invalid ID prefix length
+  NEGENTROPY_STATUS_INVALID_ID_PREFIX_LENGTH,
+  // Items not sorted or not unique
+  NEGENTROPY_STATUS_UNSORTED_ITEMS,
+  // Invalid binary snapshot
+  NEGENTROPY_STATUS_SNAPSHOT,
+  // Storage error
+  NEGENTROPY_STATUS_STORAGE,
+  // Invalid hex
+  NEGENTROPY_STATUS_INVALID_HEX,
+  // No supported protocol versions
+  NEGENTROPY_STATUS_NO_SUPPORTED_VERSIONS,
+  // Rust panic (internal error)
+  NEGENTROPY_STATUS_PANIC,
+} NegentropyStatus;
+
+// Session handle
+typedef struct Negentropy Negentropy;
+
+// Storage handle
+//
+// Sessions share the sealed items: later inserts copy them (copy-on-write).
+typedef struct NegentropyStorage NegentropyStorage;
+
+// Byte buffer owned by the caller
+//
+// Release it with [`negentropy_buffer_free`]. `data` is `NULL` when there is no value.
+typedef struct NegentropyBuffer {
+  // Bytes
+  uint8_t *data;
+  // Number of bytes
+  size_t len;
+} NegentropyBuffer;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Get a static, NUL-terminated description of a status code
+const char *negentropy_status_message(enum NegentropyStatus status);
+
+// Release a buffer returned by this library
+//
+// Does nothing if `buffer.data` is `NULL`.
+void negentropy_buffer_free(struct NegentropyBuffer buffer);
+
+// Create a new, empty storage
+//
+// Release it with [`negentropy_storage_free`]. Returns `NULL` on panic.
+struct NegentropyStorage *negentropy_storage_new(void);
+
+// Release a storage
+//
+// Sessions created from it stay valid. Does nothing if `storage` is `NULL`.
+void negentropy_storage_free(struct NegentropyStorage *storage);
+
+// Insert an item
+//
+// `id` must point to `id_len` bytes (32).
+enum NegentropyStatus negentropy_storage_insert(struct NegentropyStorage *storage,
+                                                uint64_t created_at,
+                                                const uint8_t *id,
+                                                size_t id_len);
+
+// Seal the storage: sort and deduplicate the items
+enum NegentropyStatus negentropy_storage_seal(struct NegentropyStorage *storage);
+
+// Get the number of items of a sealed storage
+enum NegentropyStatus negentropy_storage_size(const struct NegentropyStorage *storage,
+                                              size_t *size);
+
+// Create a new session over a sealed storage
+//
+// Frame size limit must be `equal to 0` or `greater than 4096`.
+//
+// On success `*session` is set to a handle to release with [`negentropy_free`].
+enum NegentropyStatus negentropy_new(const struct NegentropyStorage *storage,
+                                     uint64_t frame_size_limit,
+                                     struct Negentropy **session);
+
+// Release a session
+//
+// Does nothing if `session` is `NULL`.
+void negentropy_free(struct Negentropy *session);
+
+// Initiate reconciliation set (client method)
+enum NegentropyStatus negentropy_initiate(struct Negentropy *session,
+                                          struct NegentropyBuffer *output);
+
+// Reconcile (server method)
+enum NegentropyStatus negentropy_reconcile(struct Negentropy *session,
+                                           const uint8_t *query,
+                                           size_t query_len,
+                                           struct NegentropyBuffer *output);
+
+// Reconcile (client method)
+//
+// `have_ids` and `need_ids` are set to the concatenated 32-byte IDs.
+// `output` is set to the next message, or to a `NULL` buffer when the reconciliation is complete.
+enum NegentropyStatus negentropy_reconcile_with_ids(struct Negentropy *session,
+                                                    const uint8_t *query,
+                                                    size_t query_len,
+                                                    struct NegentropyBuffer *have_ids,
+                                                    struct NegentropyBuffer *need_ids,
+                                                    struct NegentropyBuffer *output);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* NEGENTROPY_H */
@@ -0,0 +1,550 @@
+// Copyright (c) 2023 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! C bindings of the negentropy set-reconciliation protocol.
+//!
+//! The header is generated with [cbindgen](https://github.com/mozilla/cbindgen) in
+//! `include/negentropy.h` (run `make header` after changing the API).
+//!
+//! Storages and sessions are opaque handles, created by `*_new` and released by `*_free`.
+//! Functions return a [`NegentropyStatus`] and write their results in out-parameters:
+//! the returned [`NegentropyBuffer`]s are owned by the caller and must be released with
+//! [`negentropy_buffer_free`].
+//!
+//! Panics never unwind into C: they are reported as [`NegentropyStatus::Panic`].
+//! A handle used by a call that panicked should only be released.
+
+#![allow(clippy::missing_safety_doc)]
+#![warn(missing_docs)]
+
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use negentropy::{Id, NegentropyStorageBase, NegentropyStorageVector, SnapshotError};
+
+/// Status code
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NegentropyStatus {
+    /// Success
+    Ok = 0,
+    /// Null pointer argument
+    NullPointer,
+    /// ID too big
+    IdTooBig,
+    /// Invalid ID size
+    InvalidIdSize,
+    /// Frame size limit too small
+    FrameSizeLimitTooSmall,
+    /// Not sealed
+    NotSealed,
+    /// Already sealed
+    AlreadySealed,
+    /// Already built initial message
+    AlreadyBuiltInitialMessage,
+    /// Initiator error
+    Initiator,
+    /// Non-initiator error
+    NonInitiator,
+    /// Unexpected mode
+    UnexpectedMode,
+    /// Parse ends prematurely
+    ParseEndsPrematurely,
+    /// Protocol version not found
+    ProtocolVersionNotFound,
+    /// Invalid protocol version
+    InvalidProtocolVersion,
+    /// Unsupported protocol version
+    UnsupportedProtocolVersion,
+    /// Try from slice error
+    TryFromSlice,
+    /// Bad range
+    BadRange,
+    /// Invalid ID prefix length
+    InvalidIdPrefixLength,
+    /// Items not sorted or not unique
+    UnsortedItems,
+    /// Invalid binary snapshot
+    Snapshot,
+    /// Storage error
+    Storage,
+    /// Invalid hex
+    InvalidHex,
+    /// No supported protocol versions
+    NoSupportedVersions,
+    /// Rust panic (internal error)
+    Panic,
+}
+
+impl From<negentropy::Error> for NegentropyStatus {
+    fn from(e: negentropy::Error) -> Self {
+        match e {
+            negentropy::Error::IdTooBig => Self::IdTooBig,
+            negentropy::Error::InvalidIdSize => Self::InvalidIdSize,
+            negentropy::Error::InvalidHex => Self::InvalidHex,
+            negentropy::Error::FrameSizeLimitTooSmall => Self::FrameSizeLimitTooSmall,
+            negentropy::Error::NotSealed => Self::NotSealed,
+            negentropy::Error::AlreadySealed => Self::AlreadySealed,
+            negentropy::Error::AlreadyBuiltInitialMessage => Self::AlreadyBuiltInitialMessage,
+            negentropy::Error::Initiator => Self::Initiator,
+            negentropy::Error::NonInitiator => Self::NonInitiator,
+            negentropy::Error::UnexpectedMode(..) => Self::UnexpectedMode,
+            negentropy::Error::ParseEndsPrematurely => Self::ParseEndsPrematurely,
+            negentropy::Error::ProtocolVersionNotFound => Self::ProtocolVersionNotFound,
+            negentropy::Error::InvalidProtocolVersion => Self::InvalidProtocolVersion,
+            negentropy::Error::UnsupportedProtocolVersion => Self::UnsupportedProtocolVersion,
+            negentropy::Error::NoSupportedVersions => Self::NoSupportedVersions,
+            negentropy::Error::TryFromSlice => Self::TryFromSlice,
+            negentropy::Error::BadRange => Self::BadRange,
+            negentropy::Error::InvalidIdPrefixLength => Self::InvalidIdPrefixLength,
+            negentropy::Error::UnsortedItems => Self::UnsortedItems,
+            negentropy::Error::Snapshot(..) => Self::Snapshot,
+            negentropy::Error::Storage(..) => Self::Storage,
+        }
+    }
+}
+
+impl<T> From<Result<T, negentropy::Error>> for NegentropyStatus {
+    fn from(res: Result<T, negentropy::Error>) -> Self {
+        match res {
+            Ok(..) => Self::Ok,
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl NegentropyStatus {
+    /// Error described by this status (details, like the unexpected mode, are zeroed)
+    fn error(self) -> Option<negentropy::Error> {
+        Some(match self {
+            Self::Ok | Self::NullPointer | Self::Panic => return None,
+            Self::IdTooBig => negentropy::Error::IdTooBig,
+            Self::InvalidIdSize => negentropy::Error::InvalidIdSize,
+            Self::InvalidHex => negentropy::Error::InvalidHex,
+            Self::FrameSizeLimitTooSmall => negentropy::Error::FrameSizeLimitTooSmall,
+            Self::NotSealed => negentropy::Error::NotSealed,
+            Self::AlreadySealed => negentropy::Error::AlreadySealed,
+            Self::AlreadyBuiltInitialMessage => negentropy::Error::AlreadyBuiltInitialMessage,
+            Self::Initiator => negentropy::Error::Initiator,
+            Self::NonInitiator => negentropy::Error::NonInitiator,
+            Self::UnexpectedMode => negentropy::Error::UnexpectedMode(0),
+            Self::ParseEndsPrematurely => negentropy::Error::ParseEndsPrematurely,
+            Self::ProtocolVersionNotFound => negentropy::Error::ProtocolVersionNotFound,
+            Self::InvalidProtocolVersion => negentropy::Error::InvalidProtocolVersion,
+            Self::UnsupportedProtocolVersion => negentropy::Error::UnsupportedProtocolVersion,
+            Self::NoSupportedVersions => negentropy::Error::NoSupportedVersions,
+            Self::TryFromSlice => negentropy::Error::TryFromSlice,
+            Self::BadRange => negentropy::Error::BadRange,
+            Self::InvalidIdPrefixLength => negentropy::Error::InvalidIdPrefixLength,
+            Self::UnsortedItems => negentropy::Error::UnsortedItems,
+            Self::Snapshot => negentropy::Error::Snapshot(SnapshotError::BadMagic),
+            Self::Storage => negentropy::Error::Storage(String::new()),
+        })
+    }
+
+    /// Description, from the [`negentropy::Error`] `Display` without the details (`<description>: <details>`)
+    fn message(self) -> String {
+        match self.error() {
+            Some(e) => {
+                let msg: String = e.to_string();
+                match msg.split_once(": ") {
+                    Some((description, _)) => description.to_string(),
+                    None => msg,
+                }
+            }
+            None => match self {
+                Self::NullPointer => String::from("null pointer"),
+                Self::Panic => String::from("panic"),
+                _ => String::from("ok"),
+            },
+        }
+    }
+}
+
+/// Descriptions returned by [`negentropy_status_message`], built on first use and never released
+static MESSAGES: Mutex<Vec<(NegentropyStatus, CString)>> = Mutex::new(Vec::new());
+
+/// Get a static, NUL-terminated description of a status code
+#[no_mangle]
+pub extern "C" fn negentropy_status_message(status: NegentropyStatus) -> *const c_char {
+    let msg = panic::catch_unwind(|| {
+        let mut messages = MESSAGES.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some((_, msg)) = messages.iter().find(|(s, _)| *s == status) {
+            return msg.as_ptr();
+        }
+
+        let msg: CString = CString::new(status.message()).unwrap_or_default();
+        // The string buffer doesn't move when the vector grows: the pointer stays valid
+        let ptr: *const c_char = msg.as_ptr();
+        messages.push((status, msg));
+        ptr
+    });
+    msg.unwrap_or_else(|_| b"panic\0".as_ptr() as *const c_char)
+}
+
+/// Run an entry point, reporting a panic as [`NegentropyStatus::Panic`] instead of unwinding into C
+fn guard<F>(f: F) -> NegentropyStatus
+where
+    F: FnOnce() -> NegentropyStatus,
+{
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(NegentropyStatus::Panic)
+}
+
+/// Byte buffer owned by the caller
+///
+/// Release it with [`negentropy_buffer_free`]. `data` is `NULL` when there is no value.
+#[repr(C)]
+#[derive(Debug)]
+pub struct NegentropyBuffer {
+    /// Bytes
+    pub data: *mut u8,
+    /// Number of bytes
+    pub len: usize,
+}
+
+impl NegentropyBuffer {
+    fn null() -> Self {
+        Self {
+            data: ptr::null_mut(),
+            len: 0,
+        }
+    }
+}
+
+impl From<Vec<u8>> for NegentropyBuffer {
+    fn from(bytes: Vec<u8>) -> Self {
+        let bytes: Box<[u8]> = bytes.into_boxed_slice();
+        let len: usize = bytes.len();
+        Self {
+            data: Box::into_raw(bytes) as *mut u8,
+            len,
+        }
+    }
+}
+
+impl From<Option<Vec<u8>>> for NegentropyBuffer {
+    fn from(bytes: Option<Vec<u8>>) -> Self {
+        match bytes {
+            Some(bytes) => bytes.into(),
+            None => Self::null(),
+        }
+    }
+}
+
+impl From<Vec<Id>> for NegentropyBuffer {
+    fn from(ids: Vec<Id>) -> Self {
+        let mut bytes: Vec<u8> = Vec::with_capacity(ids.len() * negentropy::ID_SIZE);
+        for id in ids.iter() {
+            bytes.extend_from_slice(id.as_bytes());
+        }
+        bytes.into()
+    }
+}
+
+/// Release a buffer returned by this library
+///
+/// Does nothing if `buffer.data` is `NULL`.
+#[no_mangle]
+pub unsafe extern "C" fn negentropy_buffer_free(buffer: NegentropyBuffer) {
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+        if !buffer.data.is_null() {
+            drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+                buffer.data,
+                buffer.len,
+            )));
+        }
+    }));
+}
+
+/// Storage handle
+///
+/// Sessions share the sealed items: later inserts copy them (copy-on-write).
+pub struct NegentropyStorage {
+    inner: Arc<NegentropyStorageVector>,
+}
+
+/// Create a new, empty storage
+///
+/// Release it with [`negentropy_storage_free`]. Returns `NULL` on panic.
+#[no_mangle]
+pub extern "C" fn negentropy_storage_new() -> *mut NegentropyStorage {
+    panic::catch_unwind(|| {
+        Box::into_raw(Box::new(NegentropyStorage {
+            inner: Arc::new(NegentropyStorageVector::new()),
+        }))
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+/// Release a storage
+///
+/// Sessions created from it stay valid. Does nothing if `storage` is `NULL`.
+#[no_mangle]
+pub unsafe extern "C" fn negentropy_storage_free(storage: *mut NegentropyStorage) {
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+        if !storage.is_null() {
+            drop(Box::from_raw(storage));
+        }
+    }));
+}
+
+/// Insert an item
+///
+/// `id` must point to `id_len` bytes (32).
+#[no_mangle]
+pub unsafe extern "C" fn negentropy_storage_insert(
+    storage: *mut NegentropyStorage,
+    created_at: u64,
+    id: *const u8,
+    id_len: usize,
+) -> NegentropyStatus {
+    guard(|| {
+        let (storage, id) = match (storage.as_mut(), bytes(id, id_len)) {
+            (Some(storage), Some(id)) => (storage, id),
+            _ => return NegentropyStatus::NullPointer,
+        };
+
+        let id: Id = match Id::from_slice(id) {
+            Ok(id) => id,
+            Err(e) => return e.into(),
+        };
+
+        Arc::make_mut(&mut storage.inner)
+            .insert(created_at, id)
+            .into()
+    })
+}
+
+/// Seal the storage: sort and deduplicate the items
+#[no_mangle]
+pub unsafe extern "C" fn negentropy_storage_seal(
+    storage: *mut NegentropyStorage,
+) -> NegentropyStatus {
+    guard(|| match storage.as_mut() {
+        Some(storage) => Arc::make_mut(&mut storage.inner).seal().into(),
+        None => NegentropyStatus::NullPointer,
+    })
+}
+
+/// Get the number of items of a sealed storage
+#[no_mangle]
+pub unsafe extern "C" fn negentropy_storage_size(
+    storage: *const NegentropyStorage,
+    size: *mut usize,
+) -> NegentropyStatus {
+    guard(|| {
+        let (storage, size) = match (storage.as_ref(), size.as_mut()) {
+            (Some(storage), Some(size)) => (storage, size),
+            _ => return NegentropyStatus::NullPointer,
+        };
+
+        match storage.inner.size() {
+            Ok(len) => {
+                *size = len;
+                NegentropyStatus::Ok
+            }
+            Err(e) => e.into(),
+        }
+    })
+}
+
+/// Session handle
+pub struct Negentropy {
+    inner: negentropy::Negentropy<'static, NegentropyStorageVector>,
+}
+
+/// Create a new session over a sealed storage
+///
+/// Frame size limit must be `equal to 0` or `greater than 4096`.
+///
+/// On success `*session` is set to a handle to release with [`negentropy_free`].
+#[no_mangle]
+pub unsafe extern "C" fn negentropy_new(
+    storage: *const NegentropyStorage,
+    frame_size_limit: u64,
+    session: *mut *mut Negentropy,
+) -> NegentropyStatus {
+    guard(|| {
+        let (storage, session) = match (storage.as_ref(), session.as_mut()) {
+            (Some(storage), Some(session)) => (storage, session),
+            _ => return NegentropyStatus::NullPointer,
+        };
+
+        if let Err(e) = storage.inner.size() {
+            return e.into();
+        }
+
+        match negentropy::Negentropy::shared(Arc::clone(&storage.inner), frame_size_limit) {
+            Ok(inner) => {
+                *session = Box::into_raw(Box::new(Negentropy { inner }));
+                NegentropyStatus::Ok
+            }
+            Err(e) => e.into(),
+        }
+    })
+}
+
+/// Release a session
+///
+/// Does nothing if `session` is `NULL`.
+#[no_mangle]
+pub unsafe extern "C" fn negentropy_free(session: *mut Negentropy) {
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+        if !session.is_null() {
+            drop(Box::from_raw(session));
+        }
+    }));
+}
+
+/// Initiate reconciliation set (client method)
+#[no_mangle]
+pub unsafe extern "C" fn negentropy_initiate(
+    session: *mut Negentropy,
+    output: *mut NegentropyBuffer,
+) -> NegentropyStatus {
+    guard(|| {
+        let (session, output) = match (session.as_mut(), output.as_mut()) {
+            (Some(session), Some(output)) => (session, output),
+            _ => return NegentropyStatus::NullPointer,
+        };
+
+        match session.inner.initiate() {
+            Ok(msg) => {
+                *output = msg.into();
+                NegentropyStatus::Ok
+            }
+            Err(e) => e.into(),
+        }
+    })
+}
+
+/// Reconcile (server method)
+#[no_mangle]
+pub unsafe extern "C" fn negentropy_reconcile(
+    session: *mut Negentropy,
+    query: *const u8,
+    query_len: usize,
+    output: *mut NegentropyBuffer,
+) -> NegentropyStatus {
+    guard(|| {
+        let (session, query, output) =
+            match (session.as_mut(), bytes(query, query_len), output.as_mut()) {
+                (Some(session), Some(query), Some(output)) => (session, query, output),
+                _ => return NegentropyStatus::NullPointer,
+            };
+
+        match session.inner.reconcile(query) {
+            Ok(msg) => {
+                *output = msg.into();
+                NegentropyStatus::Ok
+            }
+            Err(e) => e.into(),
+        }
+    })
+}
+
+/// Reconcile (client method)
+///
+/// `have_ids` and `need_ids` are set to the concatenated 32-byte IDs.
+/// `output` is set to the next message, or to a `NULL` buffer when the reconciliation is complete.
+#[no_mangle]
+pub unsafe extern "C" fn negentropy_reconcile_with_ids(
+    session: *mut Negentropy,
+    query: *const u8,
+    query_len: usize,
+    have_ids: *mut NegentropyBuffer,
+    need_ids: *mut NegentropyBuffer,
+    output: *mut NegentropyBuffer,
+) -> NegentropyStatus {
+    guard(|| {
+        let (session, query) = match (session.as_mut(), bytes(query, query_len)) {
+            (Some(session), Some(query)) => (session, query),
+            _ => return NegentropyStatus::NullPointer,
+        };
+        let (have_ids, need_ids, output) =
+            match (have_ids.as_mut(), need_ids.as_mut(), output.as_mut()) {
+                (Some(have_ids), Some(need_ids), Some(output)) => (have_ids, need_ids, output),
+                _ => return NegentropyStatus::NullPointer,
+            };
+
+        let mut haves: Vec<Id> = Vec::new();
+        let mut needs: Vec<Id> = Vec::new();
+        match session
+            .inner
+            .reconcile_with_ids(query, &mut haves, &mut needs)
+        {
+            Ok(msg) => {
+                *have_ids = haves.into();
+                *need_ids = needs.into();
+                *output = msg.into();
+                NegentropyStatus::Ok
+            }
+            Err(e) => e.into(),
+        }
+    })
+}
+
+/// Borrow `len` bytes at `data` (`NULL` is allowed for empty slices)
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> Option<&'a [u8]> {
+    if data.is_null() {
+        return if len == 0 { Some(&[]) } else { None };
+    }
+    Some(slice::from_raw_parts(data, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use super::*;
+
+    fn message(status: NegentropyStatus) -> &'static str {
+        unsafe { CStr::from_ptr(negentropy_status_message(status)) }
+            .to_str()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_status_message() {
+        let errors = vec![
+            negentropy::Error::IdTooBig,
+            negentropy::Error::InvalidHex,
+            negentropy::Error::NotSealed,
+            negentropy::Error::Initiator,
+            negentropy::Error::UnexpectedMode(7),
+            negentropy::Error::UnsupportedProtocolVersion,
+            negentropy::Error::Snapshot(SnapshotError::ChecksumMismatch),
+            negentropy::Error::Storage(String::from("database is locked")),
+        ];
+
+        for e in errors.into_iter() {
+            let display: String = e.to_string();
+            let msg: &str = message(e.into());
+            assert!(display.starts_with(msg), "{} / {}", display, msg);
+        }
+
+        assert_eq!(message(NegentropyStatus::UnexpectedMode), "Unexpected mode");
+        assert_eq!(message(NegentropyStatus::Storage), "storage error");
+        assert_eq!(message(NegentropyStatus::Ok), "ok");
+        assert_eq!(message(NegentropyStatus::Panic), "panic");
+
+        // Same pointer on every call
+        assert_eq!(
+            negentropy_status_message(NegentropyStatus::NotSealed),
+            negentropy_status_message(NegentropyStatus::NotSealed)
+        );
+    }
+
+    #[test]
+    fn test_guard() {
+        assert_eq!(
+            guard(|| NegentropyStatus::NotSealed),
+            NegentropyStatus::NotSealed
+        );
+        assert_eq!(guard(|| panic!("boom")), NegentropyStatus::Panic);
+    }
+}
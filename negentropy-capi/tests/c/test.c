@@ -0,0 +1,142 @@
+/* Copyright (c) 2023 Yuki Kishimoto */
+/* Distributed under the MIT software license */
+
+#include <stdio.h>
+#include <string.h>
+
+#include "negentropy.h"
+
+#define CHECK(expr)                                                                  \
+    do {                                                                             \
+        NegentropyStatus status_ = (expr);                                           \
+        if (status_ != NEGENTROPY_STATUS_OK) {                                       \
+            fprintf(stderr, "%s:%d: %s: %s\n", __FILE__, __LINE__, #expr,            \
+                    negentropy_status_message(status_));                             \
+            return 1;                                                                \
+        }                                                                            \
+    } while (0)
+
+#define ASSERT(expr)                                                                 \
+    do {                                                                             \
+        if (!(expr)) {                                                               \
+            fprintf(stderr, "%s:%d: assertion failed: %s\n", __FILE__, __LINE__, #expr); \
+            return 1;                                                                \
+        }                                                                            \
+    } while (0)
+
+static NegentropyStorage *storage(uint8_t begin, uint8_t end) {
+    NegentropyStorage *storage = negentropy_storage_new();
+    uint8_t id[32];
+
+    for (unsigned int i = begin; i < end; i++) {
+        memset(id, i, sizeof(id));
+        if (negentropy_storage_insert(storage, i, id, sizeof(id)) != NEGENTROPY_STATUS_OK) {
+            negentropy_storage_free(storage);
+            return NULL;
+        }
+    }
+
+    if (negentropy_storage_seal(storage) != NEGENTROPY_STATUS_OK) {
+        negentropy_storage_free(storage);
+        return NULL;
+    }
+
+    return storage;
+}
+
+static int test_reconciliation(void) {
+    NegentropyStorage *storage_client = storage(0, 100);
+    NegentropyStorage *storage_relay = storage(50, 200);
+    ASSERT(storage_client != NULL && storage_relay != NULL);
+
+    size_t size = 0;
+    CHECK(negentropy_storage_size(storage_relay, &size));
+    ASSERT(size == 150);
+
+    Negentropy *client = NULL;
+    Negentropy *relay = NULL;
+    CHECK(negentropy_new(storage_client, 0, &client));
+    CHECK(negentropy_new(storage_relay, 0, &relay));
+
+    /* Sessions keep the storage alive */
+    negentropy_storage_free(storage_client);
+    negentropy_storage_free(storage_relay);
+
+    size_t have = 0;
+    size_t need = 0;
+
+    NegentropyBuffer msg;
+    CHECK(negentropy_initiate(client, &msg));
+
+    while (msg.data != NULL) {
+        NegentropyBuffer response;
+        CHECK(negentropy_reconcile(relay, msg.data, msg.len, &response));
+        negentropy_buffer_free(msg);
+
+        NegentropyBuffer have_ids;
+        NegentropyBuffer need_ids;
+        CHECK(negentropy_reconcile_with_ids(client, response.data, response.len, &have_ids,
+                                            &need_ids, &msg));
+        negentropy_buffer_free(response);
+
+        ASSERT(have_ids.len % 32 == 0 && need_ids.len % 32 == 0);
+        for (size_t i = 0; i < have_ids.len; i += 32) {
+            ASSERT(have_ids.data[i] < 50);
+        }
+        for (size_t i = 0; i < need_ids.len; i += 32) {
+            ASSERT(need_ids.data[i] >= 100);
+        }
+        have += have_ids.len / 32;
+        need += need_ids.len / 32;
+
+        negentropy_buffer_free(have_ids);
+        negentropy_buffer_free(need_ids);
+    }
+
+    ASSERT(have == 50);
+    ASSERT(need == 100);
+
+    negentropy_free(client);
+    negentropy_free(relay);
+
+    return 0;
+}
+
+static int test_errors(void) {
+    NegentropyStorage *storage = negentropy_storage_new();
+    uint8_t id[32] = {0};
+
+    ASSERT(negentropy_storage_insert(storage, 0, id, 31) == NEGENTROPY_STATUS_INVALID_ID_SIZE);
+    ASSERT(negentropy_storage_insert(storage, 0, NULL, 32) == NEGENTROPY_STATUS_NULL_POINTER);
+
+    Negentropy *session = NULL;
+    ASSERT(negentropy_new(storage, 0, &session) == NEGENTROPY_STATUS_NOT_SEALED);
+    ASSERT(session == NULL);
+
+    CHECK(negentropy_storage_seal(storage));
+    ASSERT(negentropy_storage_seal(storage) == NEGENTROPY_STATUS_ALREADY_SEALED);
+    ASSERT(negentropy_new(storage, 100, &session) == NEGENTROPY_STATUS_FRAME_SIZE_LIMIT_TOO_SMALL);
+
+    CHECK(negentropy_new(storage, 0, &session));
+
+    const uint8_t query[] = {0x61, 0x00, 0x00, 0x05};
+    NegentropyBuffer output;
+    ASSERT(negentropy_reconcile(session, query, sizeof(query), &output) ==
+           NEGENTROPY_STATUS_UNEXPECTED_MODE);
+    ASSERT(strcmp(negentropy_status_message(NEGENTROPY_STATUS_UNEXPECTED_MODE),
+                  "Unexpected mode") == 0);
+
+    negentropy_free(session);
+    negentropy_storage_free(storage);
+
+    return 0;
+}
+
+int main(void) {
+    if (test_reconciliation() != 0 || test_errors() != 0) {
+        return 1;
+    }
+
+    printf("ok\n");
+    return 0;
+}
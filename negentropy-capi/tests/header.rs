@@ -0,0 +1,34 @@
+// Copyright (c) 2023 Yuki Kishimoto
+// Distributed under the MIT software license
+
+use std::path::PathBuf;
+
+/// Check that `include/negentropy.h` matches the API
+///
+/// Set `UPDATE_HEADER=1` to regenerate it.
+#[test]
+fn test_header_up_to_date() {
+    let crate_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let path = crate_dir.join("include").join("negentropy.h");
+
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml")).unwrap();
+    let bindings = cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()
+        .unwrap();
+
+    if std::env::var_os("UPDATE_HEADER").is_some() {
+        bindings.write_to_file(&path);
+        return;
+    }
+
+    let mut generated = Vec::new();
+    bindings.write(&mut generated);
+    let current = std::fs::read(&path).unwrap_or_default();
+    assert!(
+        generated == current,
+        "{} is outdated: run `make header`",
+        path.display()
+    );
+}
@@ -0,0 +1,33 @@
+import asyncio
+
+from negentropy import Id, Negentropy, NegentropyStorageVector, NegentropyTransport
+
+
+def storage(timestamps):
+    storage = NegentropyStorageVector()
+    for timestamp in timestamps:
+        storage.insert(timestamp, Id.from_bytes(bytes([timestamp] * 32)))
+    storage.seal()
+    return storage
+
+
+class RelayTransport(NegentropyTransport):
+    """In-process relay: replace with a websocket `NEG-MSG` round trip"""
+
+    def __init__(self, relay: Negentropy):
+        self.relay = relay
+
+    async def send(self, msg: bytes) -> bytes:
+        return await self.relay.reconcile_async(msg)
+
+
+async def main():
+    client = Negentropy(storage(range(0, 100)), None)
+    relay = Negentropy(storage(range(50, 200)), None)
+
+    res = await client.sync(RelayTransport(relay))
+    print(f"Have IDs: {len(res.have_ids)}")
+    print(f"Need IDs: {len(res.need_ids)}")
+
+
+asyncio.run(main())
@@ -0,0 +1,25 @@
+import os
+import shutil
+import subprocess
+from pathlib import Path
+
+import pytest
+
+ROOT = Path(__file__).resolve().parents[3]
+
+
+@pytest.fixture(scope="session")
+def harness() -> str:
+    """Path of the Rust `harness` binary
+
+    Set `NEGENTROPY_HARNESS` to use a prebuilt one, otherwise it's built with cargo.
+    """
+    path = os.environ.get("NEGENTROPY_HARNESS")
+    if path:
+        return path
+
+    if shutil.which("cargo") is None:
+        pytest.skip("cargo not found: set NEGENTROPY_HARNESS")
+
+    subprocess.run(["cargo", "build", "-q", "-p", "harness"], cwd=ROOT, check=True)
+    return str(ROOT / "target" / "debug" / "harness")
@@ -0,0 +1,59 @@
+"""Driver of the Rust `harness` binary (`negentropy/fuzz/harness`)"""
+
+import os
+import subprocess
+from typing import List, Optional
+
+from .vectors import Items
+
+
+class Harness:
+    """One side of a reconciliation, run by the `harness` binary"""
+
+    def __init__(self, path: str, items: Items, frame_size_limit: int = 0):
+        env = dict(os.environ, FRAMESIZELIMIT=str(frame_size_limit))
+        self.process = subprocess.Popen(
+            [path],
+            stdin=subprocess.PIPE,
+            stdout=subprocess.PIPE,
+            env=env,
+            text=True,
+        )
+        for timestamp, id in items:
+            self._write(f"item,{timestamp},{id.hex()}")
+        self._write("seal")
+        self.have_ids: List[bytes] = []
+        self.need_ids: List[bytes] = []
+
+    def _write(self, line: str):
+        self.process.stdin.write(line + "\n")
+        self.process.stdin.flush()
+
+    def _read(self) -> Optional[bytes]:
+        """Read the next message, collecting the have/need IDs. `None` when done."""
+        while True:
+            line = self.process.stdout.readline().strip()
+            cmd, _, value = line.partition(",")
+            if cmd == "msg":
+                return bytes.fromhex(value)
+            elif cmd == "have":
+                self.have_ids.append(bytes.fromhex(value))
+            elif cmd == "need":
+                self.need_ids.append(bytes.fromhex(value))
+            elif cmd == "done":
+                return None
+            else:
+                raise RuntimeError(f"unexpected harness output: {line!r}")
+
+    def initiate(self) -> bytes:
+        self._write("initiate")
+        return self._read()
+
+    def reconcile(self, msg: bytes) -> Optional[bytes]:
+        self._write(f"msg,{msg.hex()}")
+        return self._read()
+
+    def close(self):
+        self.process.stdin.close()
+        self.process.wait(timeout=10)
+        self.process.stdout.close()
@@ -0,0 +1,42 @@
+from typing import List, Optional, Tuple
+
+from negentropy import Id, Item, Negentropy, NegentropyStorageVector
+
+from .vectors import Items
+
+
+def storage(items: Items) -> NegentropyStorageVector:
+    storage = NegentropyStorageVector()
+    storage.insert_batch([Item(timestamp=timestamp, id=id) for timestamp, id in items])
+    storage.seal()
+    return storage
+
+
+def session(items: Items, frame_size_limit: int = 0) -> Negentropy:
+    return Negentropy(storage(items), frame_size_limit)
+
+
+def reconcile(
+    client: Negentropy, relay: Negentropy
+) -> Tuple[List[bytes], List[bytes], List[bytes]]:
+    """Run a whole reconciliation: return the messages, the have IDs and the need IDs"""
+    messages: List[bytes] = []
+    have_ids: List[Id] = []
+    need_ids: List[Id] = []
+
+    msg: Optional[bytes] = client.initiate()
+    while msg is not None:
+        messages.append(msg)
+        response = relay.reconcile(msg)
+        messages.append(response)
+
+        res = client.reconcile_with_ids(response)
+        have_ids.extend(res.have_ids)
+        need_ids.extend(res.need_ids)
+        msg = res.output
+
+    return (
+        messages,
+        sorted(id.as_bytes() for id in have_ids),
+        sorted(id.as_bytes() for id in need_ids),
+    )
@@ -0,0 +1,60 @@
+import pytest
+
+from negentropy import Id, Negentropy, NegentropyError, NegentropyStorageVector
+
+from .helpers import session
+from .vectors import items
+
+
+def test_invalid_id():
+    with pytest.raises(NegentropyError.InvalidIdSize):
+        Id.from_bytes(bytes(31))
+
+
+def test_storage_errors():
+    storage = NegentropyStorageVector()
+    storage.insert(0, Id.from_bytes(bytes(32)))
+
+    with pytest.raises(NegentropyError.NotSealed):
+        storage.size()
+    with pytest.raises(NegentropyError.NotSealed):
+        Negentropy(storage, None)
+
+    storage.seal()
+    with pytest.raises(NegentropyError.AlreadySealed):
+        storage.seal()
+
+
+def test_frame_size_limit_too_small():
+    storage = NegentropyStorageVector()
+    storage.seal()
+    with pytest.raises(NegentropyError.FrameSizeLimitTooSmall):
+        Negentropy(storage, 100)
+
+
+def test_session_errors():
+    client = session(items(range(10)))
+    client.initiate()
+    with pytest.raises(NegentropyError.AlreadyBuiltInitialMessage):
+        client.initiate()
+
+    relay = session(items(range(10)))
+    msg = session(items(range(5))).initiate()
+    with pytest.raises(NegentropyError.NonInitiator):
+        relay.reconcile_with_ids(msg)
+    with pytest.raises(NegentropyError.Initiator):
+        client.reconcile(relay.reconcile(msg))
+
+
+def test_protocol_errors():
+    relay = session(items(range(10)))
+
+    with pytest.raises(NegentropyError.ParseEndsPrematurely):
+        relay.reconcile(b"")
+    with pytest.raises(NegentropyError.InvalidProtocolVersion):
+        relay.reconcile(bytes([0x10]))
+    with pytest.raises(NegentropyError.ParseEndsPrematurely):
+        relay.reconcile(bytes([0x61, 0x00, 0x00, 0x01]))
+    with pytest.raises(NegentropyError.UnexpectedMode) as e:
+        relay.reconcile(bytes([0x61, 0x00, 0x00, 0x05]))
+    assert e.value.mode == 5
@@ -0,0 +1,63 @@
+"""Cross-check of the Python bindings against the Rust `harness` binary"""
+
+from typing import List, Optional
+
+import pytest
+
+from .harness import Harness
+from .helpers import reconcile, session
+from .vectors import VECTORS
+
+
+def reconcile_harness(client: Harness, relay: Harness) -> List[bytes]:
+    messages: List[bytes] = []
+
+    msg: Optional[bytes] = client.initiate()
+    while msg is not None:
+        messages.append(msg)
+        response = relay.reconcile(msg)
+        messages.append(response)
+        msg = client.reconcile(response)
+
+    return messages
+
+
+@pytest.mark.parametrize("vector", VECTORS, ids=lambda v: v.name)
+def test_same_messages_as_harness(harness, vector):
+    client = Harness(harness, vector.client, vector.frame_size_limit)
+    relay = Harness(harness, vector.relay, vector.frame_size_limit)
+    try:
+        expected = reconcile_harness(client, relay)
+    finally:
+        client.close()
+        relay.close()
+
+    messages, have, need = reconcile(
+        session(vector.client, vector.frame_size_limit),
+        session(vector.relay, vector.frame_size_limit),
+    )
+
+    assert messages == expected
+    assert have == sorted(client.have_ids) == vector.have
+    assert need == sorted(client.need_ids) == vector.need
+
+
+@pytest.mark.parametrize("vector", VECTORS, ids=lambda v: v.name)
+def test_python_client_harness_relay(harness, vector):
+    client = session(vector.client, vector.frame_size_limit)
+    relay = Harness(harness, vector.relay, vector.frame_size_limit)
+
+    have = []
+    need = []
+    try:
+        msg: Optional[bytes] = client.initiate()
+        while msg is not None:
+            res = client.reconcile_with_ids(relay.reconcile(msg))
+            have.extend(id.as_bytes() for id in res.have_ids)
+            need.extend(id.as_bytes() for id in res.need_ids)
+            msg = res.output
+    finally:
+        relay.close()
+
+    assert sorted(have) == vector.have
+    assert sorted(need) == vector.need
@@ -0,0 +1,93 @@
+import asyncio
+
+import pytest
+
+from negentropy import Id, Item, Negentropy, NegentropyStorageVector, NegentropyTransport
+
+from .helpers import reconcile, session, storage
+from .vectors import VECTORS, items
+
+
+def test_reconciliation_set():
+    client = session([(0, bytes([0xAA] * 32)), (1, bytes([0xBB] * 32))])
+    relay = session(
+        [
+            (0, bytes([0xAA] * 32)),
+            (2, bytes([0xCC] * 32)),
+            (3, bytes([0x11] * 32)),
+            (5, bytes([0x22] * 32)),
+            (10, bytes([0x33] * 32)),
+        ]
+    )
+
+    init_output = client.initiate()
+    reconcile_output = relay.reconcile(init_output)
+    res = client.reconcile_with_ids(reconcile_output)
+
+    assert res.output is None
+    assert [id.as_bytes() for id in res.have_ids] == [bytes([0xBB] * 32)]
+    assert sorted(id.as_bytes() for id in res.need_ids) == [
+        bytes([0x11] * 32),
+        bytes([0x22] * 32),
+        bytes([0x33] * 32),
+        bytes([0xCC] * 32),
+    ]
+
+
+@pytest.mark.parametrize("vector", VECTORS, ids=lambda v: v.name)
+def test_full_reconciliation(vector):
+    client = session(vector.client, vector.frame_size_limit)
+    relay = session(vector.relay, vector.frame_size_limit)
+
+    messages, have, need = reconcile(client, relay)
+
+    assert have == vector.have
+    assert need == vector.need
+    if vector.frame_size_limit:
+        assert all(len(msg) <= vector.frame_size_limit for msg in messages)
+
+
+def test_storage():
+    storage = NegentropyStorageVector()
+    for timestamp, id in reversed(items(range(100))):
+        storage.insert(timestamp, Id.from_bytes(id))
+    storage.seal()
+    assert storage.size() == 100
+
+    # Same set, built in bulk
+    client = Negentropy(storage, None)
+    relay = session(items(range(100)))
+    _, have, need = reconcile(client, relay)
+    assert have == [] and need == []
+
+
+def test_sessions_keep_snapshot():
+    vector = storage(items(range(100)))
+    relay = Negentropy(vector, None)
+
+    # Later writes don't affect the running session
+    vector.insert_batch([Item(timestamp=1_000, id=bytes(32))])
+    assert vector.size() == 101
+
+    _, have, need = reconcile(session([]), relay)
+    assert have == []
+    assert len(need) == 100
+
+
+class LocalTransport(NegentropyTransport):
+    def __init__(self, relay: Negentropy):
+        self.relay = relay
+
+    async def send(self, msg: bytes) -> bytes:
+        return await self.relay.reconcile_async(msg)
+
+
+def test_sync():
+    vector = VECTORS[5]
+    client = session(vector.client)
+    transport = LocalTransport(session(vector.relay))
+
+    res = asyncio.run(client.sync(transport))
+
+    assert sorted(id.as_bytes() for id in res.have_ids) == vector.have
+    assert sorted(id.as_bytes() for id in res.need_ids) == vector.need
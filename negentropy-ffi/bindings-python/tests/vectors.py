@@ -0,0 +1,63 @@
+"""Test vectors shared by the Python bindings and the Rust `harness` binary"""
+
+import hashlib
+from dataclasses import dataclass, field
+from typing import List, Tuple
+
+Items = List[Tuple[int, bytes]]
+
+
+def item_id(i: int) -> bytes:
+    return hashlib.sha256(i.to_bytes(8, "big")).digest()
+
+
+def items(indexes) -> Items:
+    return [(i // 3, item_id(i)) for i in indexes]
+
+
+@dataclass
+class Vector:
+    name: str
+    client: Items
+    relay: Items
+    frame_size_limit: int = 0
+    have: List[bytes] = field(init=False)
+    need: List[bytes] = field(init=False)
+
+    def __post_init__(self):
+        client = {id for _, id in self.client}
+        relay = {id for _, id in self.relay}
+        self.have = sorted(client - relay)
+        self.need = sorted(relay - client)
+
+
+VECTORS = [
+    # Same as `test_reconciliation_set` in the `negentropy` crate
+    Vector(
+        "reconciliation-set",
+        client=[(0, bytes([0xAA] * 32)), (1, bytes([0xBB] * 32))],
+        relay=[
+            (0, bytes([0xAA] * 32)),
+            (2, bytes([0xCC] * 32)),
+            (3, bytes([0x11] * 32)),
+            (5, bytes([0x22] * 32)),
+            (10, bytes([0x33] * 32)),
+        ],
+    ),
+    Vector("empty", client=[], relay=[]),
+    Vector("empty-client", client=[], relay=items(range(1_000))),
+    Vector("empty-relay", client=items(range(1_000)), relay=[]),
+    Vector("equal", client=items(range(5_000)), relay=items(range(5_000))),
+    Vector("overlap", client=items(range(0, 6_000)), relay=items(range(4_000, 10_000))),
+    Vector(
+        "interleaved",
+        client=items(range(0, 10_000, 2)),
+        relay=items(range(0, 10_000, 3)),
+    ),
+    Vector(
+        "frame-size-limit",
+        client=items(range(0, 20_000, 2)),
+        relay=items(range(0, 20_000, 5)),
+        frame_size_limit=4_097,
+    ),
+]
@@ -8,8 +8,32 @@ use uniffi::Error;
 
 pub type Result<T, E = NegentropyError> = std::result::Result<T, E>;
 
-#[derive(Debug, Error)]
+/// Negentropy error
+///
+/// Mirrors the `negentropy` errors, plus a generic one for binding errors.
+#[derive(Debug, PartialEq, Eq, Error)]
 pub enum NegentropyError {
+    IdTooBig,
+    InvalidIdSize,
+    InvalidHex,
+    FrameSizeLimitTooSmall,
+    NotSealed,
+    AlreadySealed,
+    AlreadyBuiltInitialMessage,
+    Initiator,
+    NonInitiator,
+    UnexpectedMode { mode: u64 },
+    ParseEndsPrematurely,
+    ProtocolVersionNotFound,
+    InvalidProtocolVersion,
+    UnsupportedProtocolVersion,
+    NoSupportedVersions,
+    TryFromSlice,
+    BadRange,
+    InvalidIdPrefixLength,
+    UnsortedItems,
+    Snapshot { err: SnapshotError },
+    Storage { err: String },
     Generic { err: String },
 }
 
@@ -18,6 +42,41 @@ impl std::error::Error for NegentropyError {}
 impl fmt::Display for NegentropyError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            Self::IdTooBig => write!(f, "{}", negentropy::Error::IdTooBig),
+            Self::InvalidIdSize => write!(f, "{}", negentropy::Error::InvalidIdSize),
+            Self::InvalidHex => write!(f, "{}", negentropy::Error::InvalidHex),
+            Self::FrameSizeLimitTooSmall => {
+                write!(f, "{}", negentropy::Error::FrameSizeLimitTooSmall)
+            }
+            Self::NotSealed => write!(f, "{}", negentropy::Error::NotSealed),
+            Self::AlreadySealed => write!(f, "{}", negentropy::Error::AlreadySealed),
+            Self::AlreadyBuiltInitialMessage => {
+                write!(f, "{}", negentropy::Error::AlreadyBuiltInitialMessage)
+            }
+            Self::Initiator => write!(f, "{}", negentropy::Error::Initiator),
+            Self::NonInitiator => write!(f, "{}", negentropy::Error::NonInitiator),
+            Self::UnexpectedMode { mode } => {
+                write!(f, "{}", negentropy::Error::UnexpectedMode(*mode))
+            }
+            Self::ParseEndsPrematurely => write!(f, "{}", negentropy::Error::ParseEndsPrematurely),
+            Self::ProtocolVersionNotFound => {
+                write!(f, "{}", negentropy::Error::ProtocolVersionNotFound)
+            }
+            Self::InvalidProtocolVersion => {
+                write!(f, "{}", negentropy::Error::InvalidProtocolVersion)
+            }
+            Self::UnsupportedProtocolVersion => {
+                write!(f, "{}", negentropy::Error::UnsupportedProtocolVersion)
+            }
+            Self::NoSupportedVersions => write!(f, "{}", negentropy::Error::NoSupportedVersions),
+            Self::TryFromSlice => write!(f, "{}", negentropy::Error::TryFromSlice),
+            Self::BadRange => write!(f, "{}", negentropy::Error::BadRange),
+            Self::InvalidIdPrefixLength => {
+                write!(f, "{}", negentropy::Error::InvalidIdPrefixLength)
+            }
+            Self::UnsortedItems => write!(f, "{}", negentropy::Error::UnsortedItems),
+            Self::Snapshot { err } => write!(f, "invalid snapshot: {err}"),
+            Self::Storage { err } => write!(f, "storage error: {err}"),
             Self::Generic { err } => write!(f, "{err}"),
         }
     }
@@ -31,6 +90,265 @@ impl<T> From<PoisonError<T>> for NegentropyError {
 
 impl From<negentropy::Error> for NegentropyError {
     fn from(e: negentropy::Error) -> Self {
-        Self::Generic { err: e.to_string() }
+        match e {
+            negentropy::Error::IdTooBig => Self::IdTooBig,
+            negentropy::Error::InvalidIdSize => Self::InvalidIdSize,
+            negentropy::Error::InvalidHex => Self::InvalidHex,
+            negentropy::Error::FrameSizeLimitTooSmall => Self::FrameSizeLimitTooSmall,
+            negentropy::Error::NotSealed => Self::NotSealed,
+            negentropy::Error::AlreadySealed => Self::AlreadySealed,
+            negentropy::Error::AlreadyBuiltInitialMessage => Self::AlreadyBuiltInitialMessage,
+            negentropy::Error::Initiator => Self::Initiator,
+            negentropy::Error::NonInitiator => Self::NonInitiator,
+            negentropy::Error::UnexpectedMode(mode) => Self::UnexpectedMode { mode },
+            negentropy::Error::ParseEndsPrematurely => Self::ParseEndsPrematurely,
+            negentropy::Error::ProtocolVersionNotFound => Self::ProtocolVersionNotFound,
+            negentropy::Error::InvalidProtocolVersion => Self::InvalidProtocolVersion,
+            negentropy::Error::UnsupportedProtocolVersion => Self::UnsupportedProtocolVersion,
+            negentropy::Error::NoSupportedVersions => Self::NoSupportedVersions,
+            negentropy::Error::TryFromSlice => Self::TryFromSlice,
+            negentropy::Error::BadRange => Self::BadRange,
+            negentropy::Error::InvalidIdPrefixLength => Self::InvalidIdPrefixLength,
+            negentropy::Error::UnsortedItems => Self::UnsortedItems,
+            negentropy::Error::Snapshot(e) => Self::Snapshot { err: e.into() },
+            negentropy::Error::Storage(err) => Self::Storage { err },
+        }
+    }
+}
+
+/// Invalid binary snapshot
+///
+/// Mirrors [`negentropy::SnapshotError`].
+#[derive(Debug, PartialEq, Eq, Error)]
+pub enum SnapshotError {
+    BadMagic,
+    UnsupportedVersion { version: u8 },
+    UnknownFlags { flags: u8 },
+    IdSizeMismatch { size: u16 },
+    Truncated,
+    UnsortedItems,
+    AccumulatorMismatch,
+    ChecksumMismatch,
+    TrailingBytes,
+    Io { kind: String },
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadMagic => write!(f, "{}", negentropy::SnapshotError::BadMagic),
+            Self::UnsupportedVersion { version } => write!(
+                f,
+                "{}",
+                negentropy::SnapshotError::UnsupportedVersion(*version)
+            ),
+            Self::UnknownFlags { flags } => {
+                write!(f, "{}", negentropy::SnapshotError::UnknownFlags(*flags))
+            }
+            Self::IdSizeMismatch { size } => {
+                write!(f, "{}", negentropy::SnapshotError::IdSizeMismatch(*size))
+            }
+            Self::Truncated => write!(f, "{}", negentropy::SnapshotError::Truncated),
+            Self::UnsortedItems => write!(f, "{}", negentropy::SnapshotError::UnsortedItems),
+            Self::AccumulatorMismatch => {
+                write!(f, "{}", negentropy::SnapshotError::AccumulatorMismatch)
+            }
+            Self::ChecksumMismatch => write!(f, "{}", negentropy::SnapshotError::ChecksumMismatch),
+            Self::TrailingBytes => write!(f, "{}", negentropy::SnapshotError::TrailingBytes),
+            Self::Io { kind } => write!(f, "I/O error: {kind}"),
+        }
+    }
+}
+
+impl From<negentropy::SnapshotError> for SnapshotError {
+    fn from(e: negentropy::SnapshotError) -> Self {
+        match e {
+            negentropy::SnapshotError::BadMagic => Self::BadMagic,
+            negentropy::SnapshotError::UnsupportedVersion(version) => {
+                Self::UnsupportedVersion { version }
+            }
+            negentropy::SnapshotError::UnknownFlags(flags) => Self::UnknownFlags { flags },
+            negentropy::SnapshotError::IdSizeMismatch(size) => Self::IdSizeMismatch { size },
+            negentropy::SnapshotError::Truncated => Self::Truncated,
+            negentropy::SnapshotError::UnsortedItems => Self::UnsortedItems,
+            negentropy::SnapshotError::AccumulatorMismatch => Self::AccumulatorMismatch,
+            negentropy::SnapshotError::ChecksumMismatch => Self::ChecksumMismatch,
+            negentropy::SnapshotError::TrailingBytes => Self::TrailingBytes,
+            negentropy::SnapshotError::Io(kind) => Self::Io {
+                kind: format!("{kind:?}"),
+            },
+        }
+    }
+}
+
+impl From<uniffi::UnexpectedUniFFICallbackError> for NegentropyError {
+    fn from(e: uniffi::UnexpectedUniFFICallbackError) -> Self {
+        Self::Generic { err: e.reason }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_negentropy_error() {
+        let errors = vec![
+            (negentropy::Error::IdTooBig, NegentropyError::IdTooBig),
+            (
+                negentropy::Error::InvalidIdSize,
+                NegentropyError::InvalidIdSize,
+            ),
+            (negentropy::Error::InvalidHex, NegentropyError::InvalidHex),
+            (
+                negentropy::Error::FrameSizeLimitTooSmall,
+                NegentropyError::FrameSizeLimitTooSmall,
+            ),
+            (negentropy::Error::NotSealed, NegentropyError::NotSealed),
+            (
+                negentropy::Error::AlreadySealed,
+                NegentropyError::AlreadySealed,
+            ),
+            (
+                negentropy::Error::AlreadyBuiltInitialMessage,
+                NegentropyError::AlreadyBuiltInitialMessage,
+            ),
+            (negentropy::Error::Initiator, NegentropyError::Initiator),
+            (
+                negentropy::Error::NonInitiator,
+                NegentropyError::NonInitiator,
+            ),
+            (
+                negentropy::Error::UnexpectedMode(7),
+                NegentropyError::UnexpectedMode { mode: 7 },
+            ),
+            (
+                negentropy::Error::ParseEndsPrematurely,
+                NegentropyError::ParseEndsPrematurely,
+            ),
+            (
+                negentropy::Error::ProtocolVersionNotFound,
+                NegentropyError::ProtocolVersionNotFound,
+            ),
+            (
+                negentropy::Error::InvalidProtocolVersion,
+                NegentropyError::InvalidProtocolVersion,
+            ),
+            (
+                negentropy::Error::UnsupportedProtocolVersion,
+                NegentropyError::UnsupportedProtocolVersion,
+            ),
+            (
+                negentropy::Error::NoSupportedVersions,
+                NegentropyError::NoSupportedVersions,
+            ),
+            (
+                negentropy::Error::TryFromSlice,
+                NegentropyError::TryFromSlice,
+            ),
+            (negentropy::Error::BadRange, NegentropyError::BadRange),
+            (
+                negentropy::Error::InvalidIdPrefixLength,
+                NegentropyError::InvalidIdPrefixLength,
+            ),
+            (
+                negentropy::Error::UnsortedItems,
+                NegentropyError::UnsortedItems,
+            ),
+            (
+                negentropy::Error::Snapshot(negentropy::SnapshotError::ChecksumMismatch),
+                NegentropyError::Snapshot {
+                    err: SnapshotError::ChecksumMismatch,
+                },
+            ),
+            (
+                negentropy::Error::Storage(String::from("database is locked")),
+                NegentropyError::Storage {
+                    err: String::from("database is locked"),
+                },
+            ),
+        ];
+
+        for (e, expected) in errors.into_iter() {
+            let message: String = e.to_string();
+            let e: NegentropyError = e.into();
+            assert_eq!(e, expected);
+            assert_eq!(e.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_from_snapshot_error() {
+        let errors = vec![
+            (negentropy::SnapshotError::BadMagic, SnapshotError::BadMagic),
+            (
+                negentropy::SnapshotError::UnsupportedVersion(2),
+                SnapshotError::UnsupportedVersion { version: 2 },
+            ),
+            (
+                negentropy::SnapshotError::UnknownFlags(0b10),
+                SnapshotError::UnknownFlags { flags: 0b10 },
+            ),
+            (
+                negentropy::SnapshotError::IdSizeMismatch(20),
+                SnapshotError::IdSizeMismatch { size: 20 },
+            ),
+            (
+                negentropy::SnapshotError::Truncated,
+                SnapshotError::Truncated,
+            ),
+            (
+                negentropy::SnapshotError::UnsortedItems,
+                SnapshotError::UnsortedItems,
+            ),
+            (
+                negentropy::SnapshotError::AccumulatorMismatch,
+                SnapshotError::AccumulatorMismatch,
+            ),
+            (
+                negentropy::SnapshotError::ChecksumMismatch,
+                SnapshotError::ChecksumMismatch,
+            ),
+            (
+                negentropy::SnapshotError::TrailingBytes,
+                SnapshotError::TrailingBytes,
+            ),
+            (
+                negentropy::SnapshotError::Io(std::io::ErrorKind::UnexpectedEof),
+                SnapshotError::Io {
+                    kind: String::from("UnexpectedEof"),
+                },
+            ),
+        ];
+
+        for (e, expected) in errors.into_iter() {
+            let message: String = e.to_string();
+            let e: SnapshotError = e.into();
+            assert_eq!(e, expected);
+            assert_eq!(e.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn test_error_from_session() {
+        let storage = crate::NegentropyStorageVector::new();
+        assert_eq!(
+            crate::Negentropy::new(&storage, None).err(),
+            Some(NegentropyError::NotSealed)
+        );
+
+        storage.seal().unwrap();
+        assert_eq!(
+            crate::Negentropy::new(&storage, Some(100)).err(),
+            Some(NegentropyError::FrameSizeLimitTooSmall)
+        );
+
+        let negentropy = crate::Negentropy::new(&storage, None).unwrap();
+        assert_eq!(
+            negentropy.reconcile(&[0x61, 0x00, 0x00, 0x05]).err(),
+            Some(NegentropyError::UnexpectedMode { mode: 5 })
+        );
     }
 }
@@ -0,0 +1,311 @@
+// Copyright (c) 2023 Yuki Kishimoto
+// Distributed under the MIT software license
+
+use std::sync::Arc;
+
+use negentropy::FingerprintHasher;
+use uniffi::Record;
+
+use crate::error::{NegentropyError, Result};
+
+/// Number of items requested per `iterate` call
+const BATCH_SIZE: u64 = 1024;
+
+/// Item
+#[derive(Debug, Clone, PartialEq, Eq, Record)]
+pub struct Item {
+    pub timestamp: u64,
+    /// 32-byte ID
+    pub id: Vec<u8>,
+}
+
+impl From<negentropy::Item> for Item {
+    fn from(item: negentropy::Item) -> Self {
+        Self {
+            timestamp: item.timestamp,
+            id: item.id.as_bytes().to_vec(),
+        }
+    }
+}
+
+impl TryFrom<Item> for negentropy::Item {
+    type Error = negentropy::Error;
+
+    fn try_from(item: Item) -> Result<Self, Self::Error> {
+        Ok(Self::with_timestamp_and_id(
+            item.timestamp,
+            negentropy::Id::from_slice(&item.id)?,
+        ))
+    }
+}
+
+/// Storage implemented in the foreign language (e.g. backed by the app database)
+///
+/// Mirrors `NegentropyStorageBase`: items must be sorted by `(timestamp, id)`, without duplicates,
+/// and must not change during a reconciliation.
+#[uniffi::export(with_foreign)]
+pub trait NegentropyStorage: Send + Sync {
+    /// Number of items
+    fn size(&self) -> Result<u64>;
+
+    /// Item at position `i`, or `None` if out of range
+    fn get_item(&self, i: u64) -> Result<Option<Item>>;
+
+    /// Items at positions `[begin, end)`
+    ///
+    /// Called in batches: `end - begin` is never greater than 1024.
+    fn iterate(&self, begin: u64, end: u64) -> Result<Vec<Item>>;
+
+    /// First position in `[first, last)` of an item not less than `value`, or `last`
+    fn find_lower_bound(&self, first: u64, last: u64, value: Item) -> u64;
+
+    /// 16-byte fingerprint of the items at positions `[begin, end)`
+    ///
+    /// Return `None` to let it compute from `iterate`.
+    fn fingerprint(&self, begin: u64, end: u64) -> Result<Option<Vec<u8>>>;
+}
+
+/// Adapter of a [`NegentropyStorage`] to `NegentropyStorageBase`
+pub(crate) struct ForeignStorage {
+    inner: Arc<dyn NegentropyStorage>,
+}
+
+impl ForeignStorage {
+    pub(crate) fn new(inner: Arc<dyn NegentropyStorage>) -> Self {
+        Self { inner }
+    }
+}
+
+impl From<NegentropyError> for negentropy::Error {
+    fn from(e: NegentropyError) -> Self {
+        Self::Storage(e.to_string())
+    }
+}
+
+impl negentropy::NegentropyStorageBase for ForeignStorage {
+    fn size(&self) -> Result<usize, negentropy::Error> {
+        Ok(self.inner.size()? as usize)
+    }
+
+    fn get_item(&self, i: usize) -> Result<Option<negentropy::Item>, negentropy::Error> {
+        self.inner
+            .get_item(i as u64)?
+            .map(negentropy::Item::try_from)
+            .transpose()
+    }
+
+    fn iterate(
+        &self,
+        begin: usize,
+        end: usize,
+        cb: &mut dyn FnMut(negentropy::Item, usize) -> Result<bool, negentropy::Error>,
+    ) -> Result<(), negentropy::Error> {
+        if begin > end {
+            return Err(negentropy::Error::BadRange);
+        }
+
+        let mut i: u64 = begin as u64;
+        let end: u64 = end as u64;
+
+        while i < end {
+            let batch_end: u64 = end.min(i + BATCH_SIZE);
+            let items: Vec<Item> = self.inner.iterate(i, batch_end)?;
+
+            if items.len() as u64 != batch_end - i {
+                return Err(negentropy::Error::BadRange);
+            }
+
+            for item in items.into_iter() {
+                if !cb(negentropy::Item::try_from(item)?, i as usize)? {
+                    return Ok(());
+                }
+                i += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn find_lower_bound(&self, first: usize, last: usize, value: &negentropy::Bound) -> usize {
+        self.inner
+            .find_lower_bound(first as u64, last as u64, value.item.into()) as usize
+    }
+
+    fn fingerprint(
+        &self,
+        hasher: &dyn FingerprintHasher,
+        begin: usize,
+        end: usize,
+    ) -> Result<negentropy::Fingerprint, negentropy::Error> {
+        match self.inner.fingerprint(begin as u64, end as u64)? {
+            Some(fingerprint) => Ok(negentropy::Fingerprint::from_bytes(
+                fingerprint.as_slice().try_into()?,
+            )),
+            None => self
+                .accumulate(begin, end)?
+                .get_fingerprint(hasher, (end - begin) as u64),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use negentropy::NegentropyStorageBase;
+
+    use super::*;
+    use crate::{Id, Negentropy, NegentropyStorageVector};
+
+    /// Mock of a foreign database
+    #[derive(Default)]
+    struct MockStorage {
+        items: Vec<Item>,
+        iterate_calls: AtomicUsize,
+        fail: bool,
+    }
+
+    impl MockStorage {
+        fn new(range: std::ops::Range<u8>) -> Self {
+            let mut storage = negentropy::NegentropyStorageVector::new();
+            for i in range {
+                storage
+                    .insert(i as u64, negentropy::Id::from_byte_array([i; 32]))
+                    .unwrap();
+            }
+            storage.seal().unwrap();
+
+            let mut items = Vec::new();
+            storage
+                .iterate(0, storage.size().unwrap(), &mut |item, _| {
+                    items.push(item.into());
+                    Ok(true)
+                })
+                .unwrap();
+
+            Self {
+                items,
+                ..Default::default()
+            }
+        }
+    }
+
+    impl NegentropyStorage for MockStorage {
+        fn size(&self) -> Result<u64> {
+            if self.fail {
+                return Err(NegentropyError::Generic {
+                    err: String::from("database is locked"),
+                });
+            }
+
+            Ok(self.items.len() as u64)
+        }
+
+        fn get_item(&self, i: u64) -> Result<Option<Item>> {
+            Ok(self.items.get(i as usize).cloned())
+        }
+
+        fn iterate(&self, begin: u64, end: u64) -> Result<Vec<Item>> {
+            assert!(end - begin <= BATCH_SIZE);
+            self.iterate_calls.fetch_add(1, Ordering::SeqCst);
+            Ok(self.items[begin as usize..end as usize].to_vec())
+        }
+
+        fn find_lower_bound(&self, first: u64, last: u64, value: Item) -> u64 {
+            let items = &self.items[first as usize..last as usize];
+            let pos = items.partition_point(|item| {
+                (item.timestamp, item.id.as_slice()) < (value.timestamp, value.id.as_slice())
+            });
+            first + pos as u64
+        }
+
+        fn fingerprint(&self, _begin: u64, _end: u64) -> Result<Option<Vec<u8>>> {
+            Ok(None)
+        }
+    }
+
+    #[test]
+    fn test_foreign_storage_reconciliation() {
+        let storage_client = NegentropyStorageVector::new();
+        for i in 0..100u8 {
+            storage_client
+                .insert(i as u64, &Id::from_bytes(vec![i; 32]).unwrap())
+                .unwrap();
+        }
+        storage_client.seal().unwrap();
+        let client = Negentropy::new(&storage_client, None).unwrap();
+
+        let mock = Arc::new(MockStorage::new(50..200));
+        let relay = Negentropy::with_foreign_storage(mock.clone(), None).unwrap();
+
+        let mut have_ids = Vec::new();
+        let mut need_ids = Vec::new();
+        let mut msg = client.initiate().unwrap();
+        loop {
+            let response = relay.reconcile(&msg).unwrap();
+            let res = client.reconcile_with_ids(&response).unwrap();
+            have_ids.extend(res.have_ids.iter().map(|id| id.as_bytes()));
+            need_ids.extend(res.need_ids.iter().map(|id| id.as_bytes()));
+            match res.output {
+                Some(next) => msg = next,
+                None => break,
+            }
+        }
+
+        have_ids.sort();
+        need_ids.sort();
+        assert_eq!(have_ids, (0..50u8).map(|i| vec![i; 32]).collect::<Vec<_>>());
+        assert_eq!(
+            need_ids,
+            (100..200u8).map(|i| vec![i; 32]).collect::<Vec<_>>()
+        );
+        assert!(mock.iterate_calls.load(Ordering::SeqCst) > 0);
+    }
+
+    #[test]
+    fn test_foreign_storage_batches() {
+        let storage = ForeignStorage::new(Arc::new(MockStorage {
+            items: (0..3000u64)
+                .map(|i| Item {
+                    timestamp: i,
+                    id: vec![0; 32],
+                })
+                .collect(),
+            ..Default::default()
+        }));
+
+        let mut count = 0;
+        storage
+            .iterate(10, 2600, &mut |item, i| {
+                assert_eq!(item.timestamp, i as u64);
+                count += 1;
+                Ok(true)
+            })
+            .unwrap();
+        assert_eq!(count, 2590);
+
+        let mut count = 0;
+        storage
+            .iterate(0, 3000, &mut |_, _| {
+                count += 1;
+                Ok(count < 5)
+            })
+            .unwrap();
+        assert_eq!(count, 5);
+    }
+
+    #[test]
+    fn test_foreign_storage_error() {
+        let mock = MockStorage {
+            fail: true,
+            ..Default::default()
+        };
+        let storage = ForeignStorage::new(Arc::new(mock));
+        assert_eq!(
+            storage.size(),
+            Err(negentropy::Error::Storage(String::from(
+                "database is locked"
+            )))
+        );
+    }
+}
@@ -34,7 +34,20 @@ impl Id {
         })
     }
 
+    /// Parse 64-char hex
+    #[uniffi::constructor]
+    pub fn from_hex(hex: &str) -> Result<Self> {
+        Ok(Self {
+            inner: negentropy::Id::from_hex(hex)?,
+        })
+    }
+
     pub fn as_bytes(&self) -> Vec<u8> {
         self.inner.as_bytes().to_vec()
     }
+
+    /// Encode as lowercase hex
+    pub fn to_hex(&self) -> String {
+        self.inner.to_hex()
+    }
 }
@@ -8,12 +8,17 @@ use std::sync::{Arc, Mutex};
 use uniffi::{Object, Record};
 
 mod error;
+mod foreign;
 mod id;
 mod storage;
+mod sync;
 
-pub use self::error::NegentropyError;
 use self::error::Result;
+pub use self::error::{NegentropyError, SnapshotError};
+use self::foreign::ForeignStorage;
+pub use self::foreign::{Item, NegentropyStorage};
 pub use self::storage::NegentropyStorageVector;
+pub use self::sync::{NegentropyTransport, SyncResult};
 use crate::id::Id;
 
 #[derive(Record)]
@@ -23,9 +28,11 @@ pub struct ReconcileWithIds {
     pub output: Option<Vec<u8>>,
 }
 
+type DynStorage = Box<dyn negentropy::NegentropyStorageBase + Send + Sync>;
+
 #[derive(Object)]
 pub struct Negentropy {
-    inner: Mutex<negentropy::Negentropy<'static, negentropy::NegentropyStorageVector>>,
+    inner: Mutex<negentropy::Negentropy<'static, DynStorage>>,
 }
 
 #[uniffi::export]
@@ -35,12 +42,18 @@ impl Negentropy {
     /// Frame size limit must be `equal to 0` or `greater than 4096`
     #[uniffi::constructor]
     pub fn new(storage: &NegentropyStorageVector, frame_size_limit: Option<u64>) -> Result<Self> {
-        Ok(Self {
-            inner: Mutex::new(negentropy::Negentropy::owned(
-                storage.to_inner()?,
-                frame_size_limit.unwrap_or_default(),
-            )?),
-        })
+        Self::with_storage(Box::new(storage.to_shared()?), frame_size_limit)
+    }
+
+    /// Create new negentropy instance backed by a foreign storage
+    ///
+    /// Frame size limit must be `equal to 0` or `greater than 4096`
+    #[uniffi::constructor]
+    pub fn with_foreign_storage(
+        storage: Arc<dyn NegentropyStorage>,
+        frame_size_limit: Option<u64>,
+    ) -> Result<Self> {
+        Self::with_storage(Box::new(ForeignStorage::new(storage)), frame_size_limit)
     }
 
     /// Initiate reconciliation set
@@ -82,4 +95,36 @@ impl Negentropy {
     }
 }
 
+#[uniffi::export]
+impl Negentropy {
+    /// Initiate reconciliation set, on a background thread
+    pub async fn initiate_async(self: Arc<Self>) -> Result<Vec<u8>> {
+        blocking::unblock(move || self.initiate()).await
+    }
+
+    /// Reconcile (server method), on a background thread
+    pub async fn reconcile_async(self: Arc<Self>, query: Vec<u8>) -> Result<Vec<u8>> {
+        blocking::unblock(move || self.reconcile(&query)).await
+    }
+
+    /// Reconcile (client method), on a background thread
+    pub async fn reconcile_with_ids_async(
+        self: Arc<Self>,
+        query: Vec<u8>,
+    ) -> Result<ReconcileWithIds> {
+        blocking::unblock(move || self.reconcile_with_ids(&query)).await
+    }
+}
+
+impl Negentropy {
+    fn with_storage(storage: DynStorage, frame_size_limit: Option<u64>) -> Result<Self> {
+        Ok(Self {
+            inner: Mutex::new(negentropy::Negentropy::owned(
+                storage,
+                frame_size_limit.unwrap_or_default(),
+            )?),
+        })
+    }
+}
+
 uniffi::setup_scaffolding!("negentropy");
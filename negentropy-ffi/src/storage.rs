@@ -1,17 +1,18 @@
 // Copyright (c) 2023 Yuki Kishimoto
 // Distributed under the MIT software license
 
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 
 use negentropy::NegentropyStorageBase;
 use uniffi::Object;
 
 use crate::error::Result;
+use crate::foreign::Item;
 use crate::id::Id;
 
 #[derive(Object)]
 pub struct NegentropyStorageVector {
-    inner: Mutex<negentropy::NegentropyStorageVector>,
+    inner: Mutex<Arc<negentropy::NegentropyStorageVector>>,
 }
 
 #[uniffi::export]
@@ -19,37 +20,135 @@ impl NegentropyStorageVector {
     #[uniffi::constructor]
     pub fn new() -> Self {
         Self {
-            inner: Mutex::new(negentropy::NegentropyStorageVector::new()),
+            inner: Mutex::new(Arc::new(negentropy::NegentropyStorageVector::new())),
         }
     }
 
     /// Insert item
     pub fn insert(&self, created_at: u64, id: &Id) -> Result<()> {
         let mut storage = self.inner.lock()?;
-        Ok(storage.insert(created_at, **id)?)
+        Ok(Arc::make_mut(&mut storage).insert(created_at, **id)?)
+    }
+
+    /// Insert many items at once
+    ///
+    /// If the storage is sealed, the new items are merged into the existing ones
+    /// and the storage stays sealed.
+    pub fn insert_batch(&self, items: Vec<Item>) -> Result<()> {
+        let items: Vec<negentropy::Item> = items
+            .into_iter()
+            .map(negentropy::Item::try_from)
+            .collect::<Result<_, _>>()?;
+        let mut storage = self.inner.lock()?;
+        Arc::make_mut(&mut storage).insert_batch(items);
+        Ok(())
     }
 
     /// Seal
     pub fn seal(&self) -> Result<()> {
         let mut storage = self.inner.lock()?;
-        Ok(storage.seal()?)
+        Ok(Arc::make_mut(&mut storage).seal()?)
     }
 
     /// Unseal
     pub fn unseal(&self) -> Result<()> {
         let mut storage = self.inner.lock()?;
-        Ok(storage.unseal()?)
+        Ok(Arc::make_mut(&mut storage).unseal()?)
     }
 
-    fn size(&self) -> Result<u64> {
+    /// Number of items
+    pub fn size(&self) -> Result<u64> {
         let storage = self.inner.lock()?;
         Ok(storage.size()? as u64)
     }
 }
 
 impl NegentropyStorageVector {
-    pub(crate) fn to_inner(&self) -> Result<negentropy::NegentropyStorageVector> {
+    /// Get a shared reference to the sealed storage
+    ///
+    /// Sessions keep reading the snapshot they were created with:
+    /// later writes copy it instead of mutating it (copy-on-write).
+    pub(crate) fn to_shared(&self) -> Result<Arc<negentropy::NegentropyStorageVector>> {
         let storage = self.inner.lock()?;
-        Ok(storage.to_owned())
+        // Fails with `NotSealed` if not sealed
+        storage.size()?;
+        Ok(Arc::clone(&storage))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Negentropy;
+
+    fn item(i: u8) -> Item {
+        Item {
+            timestamp: i as u64,
+            id: vec![i; 32],
+        }
+    }
+
+    #[test]
+    fn test_insert_batch_and_size() {
+        let storage = NegentropyStorageVector::new();
+        storage
+            .insert_batch((0..10).rev().map(item).collect())
+            .unwrap();
+        assert_eq!(
+            storage.size().err(),
+            Some(crate::NegentropyError::NotSealed)
+        );
+
+        storage.seal().unwrap();
+        assert_eq!(storage.size().unwrap(), 10);
+
+        // Merged into the sealed items, skipping duplicates
+        storage.insert_batch((5..15).map(item).collect()).unwrap();
+        assert_eq!(storage.size().unwrap(), 15);
+
+        let mut invalid = item(20);
+        invalid.id.pop();
+        assert_eq!(
+            storage.insert_batch(vec![item(16), invalid]).err(),
+            Some(crate::NegentropyError::InvalidIdSize)
+        );
+        assert_eq!(storage.size().unwrap(), 15);
+    }
+
+    #[test]
+    fn test_sessions_share_snapshot() {
+        let storage = NegentropyStorageVector::new();
+        storage.insert_batch((0..100).map(item).collect()).unwrap();
+        storage.seal().unwrap();
+
+        // No copy per session
+        let snapshot = storage.to_shared().unwrap();
+        let relay = Negentropy::new(&storage, None).unwrap();
+        assert!(Arc::ptr_eq(&snapshot, &storage.to_shared().unwrap()));
+        assert_eq!(Arc::strong_count(&snapshot), 3);
+
+        // Later writes don't affect the running session
+        storage
+            .insert_batch((100..150).map(item).collect())
+            .unwrap();
+        assert_eq!(storage.size().unwrap(), 150);
+        assert_eq!(snapshot.size().unwrap(), 100);
+
+        let client = NegentropyStorageVector::new();
+        client.seal().unwrap();
+        let client = Negentropy::new(&client, None).unwrap();
+
+        let mut need_ids = Vec::new();
+        let mut msg = client.initiate().unwrap();
+        loop {
+            let response = relay.reconcile(&msg).unwrap();
+            let res = client.reconcile_with_ids(&response).unwrap();
+            need_ids.extend(res.need_ids);
+            match res.output {
+                Some(next) => msg = next,
+                None => break,
+            }
+        }
+        assert_eq!(need_ids.len(), 100);
     }
 }
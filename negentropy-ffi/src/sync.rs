@@ -0,0 +1,146 @@
+// Copyright (c) 2023 Yuki Kishimoto
+// Distributed under the MIT software license
+
+use std::sync::Arc;
+
+use uniffi::Record;
+
+use crate::error::Result;
+use crate::id::Id;
+use crate::Negentropy;
+
+/// Transport to the other side of the reconciliation (e.g. a relay websocket)
+#[uniffi::export(with_foreign)]
+#[async_trait::async_trait]
+pub trait NegentropyTransport: Send + Sync {
+    /// Send a message and wait for the response
+    async fn send(&self, msg: Vec<u8>) -> Result<Vec<u8>>;
+}
+
+/// Result of a whole reconciliation
+#[derive(Record)]
+pub struct SyncResult {
+    /// IDs we have and the other side needs
+    pub have_ids: Vec<Arc<Id>>,
+    /// IDs we need from the other side
+    pub need_ids: Vec<Arc<Id>>,
+}
+
+#[uniffi::export]
+impl Negentropy {
+    /// Run a whole reconciliation (client method)
+    ///
+    /// Messages are exchanged through the `transport` and processed on a background thread.
+    pub async fn sync(
+        self: Arc<Self>,
+        transport: Arc<dyn NegentropyTransport>,
+    ) -> Result<SyncResult> {
+        let mut have_ids: Vec<Arc<Id>> = Vec::new();
+        let mut need_ids: Vec<Arc<Id>> = Vec::new();
+
+        let mut msg: Vec<u8> = self.clone().initiate_async().await?;
+        loop {
+            let response: Vec<u8> = transport.send(msg).await?;
+            let res = self.clone().reconcile_with_ids_async(response).await?;
+
+            have_ids.extend(res.have_ids);
+            need_ids.extend(res.need_ids);
+
+            match res.output {
+                Some(next) => msg = next,
+                None => break,
+            }
+        }
+
+        Ok(SyncResult { have_ids, need_ids })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Item, NegentropyError, NegentropyStorageVector};
+
+    /// Relay reachable in-process
+    struct LocalTransport {
+        relay: Arc<Negentropy>,
+    }
+
+    #[async_trait::async_trait]
+    impl NegentropyTransport for LocalTransport {
+        async fn send(&self, msg: Vec<u8>) -> Result<Vec<u8>> {
+            self.relay.clone().reconcile_async(msg).await
+        }
+    }
+
+    struct FailingTransport;
+
+    #[async_trait::async_trait]
+    impl NegentropyTransport for FailingTransport {
+        async fn send(&self, _msg: Vec<u8>) -> Result<Vec<u8>> {
+            Err(NegentropyError::Generic {
+                err: String::from("connection closed"),
+            })
+        }
+    }
+
+    fn negentropy(range: std::ops::Range<u8>) -> Arc<Negentropy> {
+        let storage = NegentropyStorageVector::new();
+        storage
+            .insert_batch(
+                range
+                    .map(|i| Item {
+                        timestamp: i as u64,
+                        id: vec![i; 32],
+                    })
+                    .collect(),
+            )
+            .unwrap();
+        storage.seal().unwrap();
+        Arc::new(Negentropy::new(&storage, None).unwrap())
+    }
+
+    #[test]
+    fn test_sync() {
+        let client = negentropy(0..100);
+        let transport = Arc::new(LocalTransport {
+            relay: negentropy(50..200),
+        });
+
+        let res = pollster::block_on(client.sync(transport)).unwrap();
+
+        let mut have_ids: Vec<Vec<u8>> = res.have_ids.iter().map(|id| id.as_bytes()).collect();
+        let mut need_ids: Vec<Vec<u8>> = res.need_ids.iter().map(|id| id.as_bytes()).collect();
+        have_ids.sort();
+        need_ids.sort();
+        assert_eq!(have_ids, (0..50u8).map(|i| vec![i; 32]).collect::<Vec<_>>());
+        assert_eq!(
+            need_ids,
+            (100..200u8).map(|i| vec![i; 32]).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_sync_transport_error() {
+        let client = negentropy(0..10);
+        assert_eq!(
+            pollster::block_on(client.sync(Arc::new(FailingTransport))).err(),
+            Some(NegentropyError::Generic {
+                err: String::from("connection closed"),
+            })
+        );
+    }
+
+    #[test]
+    fn test_async_methods() {
+        let client = negentropy(0..10);
+        let relay = negentropy(5..20);
+
+        let msg = pollster::block_on(client.clone().initiate_async()).unwrap();
+        let response = pollster::block_on(relay.reconcile_async(msg)).unwrap();
+        let res = pollster::block_on(client.reconcile_with_ids_async(response)).unwrap();
+        assert_eq!(res.have_ids.len(), 5);
+        assert_eq!(res.need_ids.len(), 10);
+        assert!(res.output.is_none());
+    }
+}
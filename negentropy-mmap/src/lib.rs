@@ -0,0 +1,226 @@
+// Copyright (c) 2023 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! Memory-mapped read-only storage for the negentropy set-reconciliation protocol.
+//!
+//! [`NegentropyStorageMmap`] serves a [binary snapshot](negentropy::binary) file
+//! (e.g. written with [`NegentropyStorageVector::save_to`](negentropy::NegentropyStorageVector::save_to))
+//! in place: records are 40 bytes (8-byte timestamp and 32-byte ID), decoded on access and
+//! binary-searched, without loading the set in memory.
+//!
+//! The mapping is the only `unsafe` code, isolated in this crate: `negentropy` itself
+//! is `#![forbid(unsafe_code)]` and parses the mapped bytes with [`NegentropyStorageBytes`].
+
+#![deny(unsafe_code)]
+#![warn(missing_docs)]
+
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+
+use memmap2::Mmap;
+use negentropy::{
+    Accumulator, Bound, Item, NegentropyStorageBase, NegentropyStorageBytes, ID_SIZE,
+};
+
+/// Error
+#[derive(Debug)]
+pub enum Error {
+    /// I/O error
+    Io(std::io::Error),
+    /// Negentropy error
+    Negentropy(negentropy::Error),
+}
+
+impl std::error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::Negentropy(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<negentropy::Error> for Error {
+    fn from(e: negentropy::Error) -> Self {
+        Self::Negentropy(e)
+    }
+}
+
+/// Memory-mapped read-only storage
+///
+/// The file must not be modified while mapped: write new snapshots to another file and rename it.
+#[derive(Debug)]
+pub struct NegentropyStorageMmap<const N: usize = ID_SIZE> {
+    inner: NegentropyStorageBytes<Mmap, N>,
+}
+
+impl NegentropyStorageMmap {
+    /// Open a snapshot file
+    ///
+    /// Only the header and the length are checked: see [`NegentropyStorageMmap::validate`].
+    ///
+    /// For ID sizes other than [`ID_SIZE`] use [`NegentropyStorageMmap::from_file`].
+    pub fn open<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let file: File = File::open(path)?;
+        Self::from_file(&file)
+    }
+}
+
+impl<const N: usize> NegentropyStorageMmap<N> {
+    /// Map a snapshot file of `N`-byte IDs
+    ///
+    /// Only the header and the length are checked: see [`NegentropyStorageMmap::validate`].
+    pub fn from_file(file: &File) -> Result<Self, Error> {
+        let mmap: Mmap = map(file)?;
+        Ok(Self {
+            inner: NegentropyStorageBytes::parse(mmap)?,
+        })
+    }
+
+    /// Validate the records order, the prefix accumulators (if any) and the checksum in `O(n)`
+    pub fn validate(&self) -> Result<(), Error> {
+        Ok(self.inner.validate()?)
+    }
+
+    /// Whether the snapshot has the prefix accumulators (`O(1)` fingerprints)
+    #[inline]
+    pub fn has_prefix_accumulators(&self) -> bool {
+        self.inner.has_prefix_accumulators()
+    }
+}
+
+#[allow(unsafe_code)]
+fn map(file: &File) -> Result<Mmap, Error> {
+    // SAFETY: the mapping is read-only and the file must not be modified while mapped
+    // (see `NegentropyStorageMmap` docs). Bytes are parsed and bounds-checked by `negentropy`.
+    Ok(unsafe { Mmap::map(file)? })
+}
+
+impl<const N: usize> NegentropyStorageBase<N> for NegentropyStorageMmap<N> {
+    fn size(&self) -> Result<usize, negentropy::Error> {
+        self.inner.size()
+    }
+
+    fn get_item(&self, i: usize) -> Result<Option<Item<N>>, negentropy::Error> {
+        self.inner.get_item(i)
+    }
+
+    fn iterate(
+        &self,
+        begin: usize,
+        end: usize,
+        cb: &mut dyn FnMut(Item<N>, usize) -> Result<bool, negentropy::Error>,
+    ) -> Result<(), negentropy::Error> {
+        self.inner.iterate(begin, end, cb)
+    }
+
+    fn find_lower_bound(&self, first: usize, last: usize, value: &Bound<N>) -> usize {
+        self.inner.find_lower_bound(first, last, value)
+    }
+
+    fn accumulate(&self, begin: usize, end: usize) -> Result<Accumulator<N>, negentropy::Error> {
+        self.inner.accumulate(begin, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use negentropy::{Id, Negentropy, NegentropyStorageVector, SnapshotError};
+
+    use super::*;
+
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            Self(std::env::temp_dir().join(format!(
+                "negentropy-mmap-{}-{}",
+                std::process::id(),
+                name
+            )))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn storage(range: std::ops::Range<u64>) -> NegentropyStorageVector {
+        range
+            .map(|i| {
+                let mut id = [0u8; 32];
+                id[..8].copy_from_slice(&i.wrapping_mul(0x9E37_79B9_7F4A_7C15).to_be_bytes());
+                Item::with_timestamp_and_id(i, Id::from_byte_array(id))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_mmap_reconciliation() {
+        for prefix_accumulators in [false, true] {
+            let file = TempFile::new(&format!("reconciliation-{}", prefix_accumulators));
+            storage(0..10_000)
+                .save_to(File::create(&file.0).unwrap(), prefix_accumulators)
+                .unwrap();
+
+            let storage_relay = NegentropyStorageMmap::open(&file.0).unwrap();
+            storage_relay.validate().unwrap();
+            assert_eq!(storage_relay.has_prefix_accumulators(), prefix_accumulators);
+            assert_eq!(storage_relay.size().unwrap(), 10_000);
+
+            let mut client = Negentropy::owned(storage(5_000..12_000), 0).unwrap();
+            let mut relay = Negentropy::borrowed(&storage_relay, 0).unwrap();
+
+            let mut have_ids = Vec::new();
+            let mut need_ids = Vec::new();
+            let mut msg = client.initiate().unwrap();
+            loop {
+                let response = relay.reconcile(&msg).unwrap();
+                match client
+                    .reconcile_with_ids(&response, &mut have_ids, &mut need_ids)
+                    .unwrap()
+                {
+                    Some(next) => msg = next,
+                    None => break,
+                }
+            }
+
+            assert_eq!(have_ids.len(), 2_000);
+            assert_eq!(need_ids.len(), 5_000);
+        }
+    }
+
+    #[test]
+    fn test_mmap_corrupted() {
+        let file = TempFile::new("corrupted");
+        let mut bytes = storage(0..100).to_bytes(false).unwrap();
+        bytes.pop();
+        std::fs::write(&file.0, &bytes).unwrap();
+
+        match NegentropyStorageMmap::open(&file.0) {
+            Err(Error::Negentropy(negentropy::Error::Snapshot(SnapshotError::Truncated))) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        assert!(matches!(
+            NegentropyStorageMmap::open(file.0.with_extension("missing")),
+            Err(Error::Io(..))
+        ));
+    }
+}
@@ -0,0 +1,6 @@
+// Copyright (c) 2023 Yuki Kishimoto
+// Distributed under the MIT software license
+
+fn main() {
+    napi_build::setup();
+}
@@ -0,0 +1,73 @@
+// Copyright (c) 2023 Yuki Kishimoto
+// Distributed under the MIT software license
+
+/// <reference types="node" />
+
+/** Storage */
+export class NegentropyStorageVector {
+  /** Create new, empty storage */
+  constructor()
+  /**
+   * Insert item
+   *
+   * `createdAt` must be an integer between `0` and `Number.MAX_SAFE_INTEGER` (`2^53 - 1`).
+   */
+  insert(createdAt: number, id: Buffer): void
+  /** Seal */
+  seal(): void
+  /** Unseal */
+  unseal(): void
+  /** Number of items */
+  size(): number
+}
+
+/** Result of `reconcileWithIds` */
+export interface ReconcileWithIds {
+  /** IDs we have and the other side needs */
+  haveIds: Array<Buffer>
+  /** IDs we need from the other side */
+  needIds: Array<Buffer>
+  /** Next message, or `null` when the reconciliation is complete */
+  output?: Buffer | null
+}
+
+/** Negentropy session */
+export class Negentropy {
+  /**
+   * Create new negentropy instance
+   *
+   * The storage must be sealed: the session keeps reading the items sealed when it was created,
+   * even if the storage is later modified.
+   *
+   * Frame size limit must be `equal to 0` or `greater than 4096`
+   */
+  constructor(storage: NegentropyStorageVector, frameSizeLimit?: number | null)
+  /** Initiate reconciliation set */
+  initiate(): Buffer
+  /** Whether this instance is the initiator */
+  isInitiator(): boolean
+  /** Set initiator: for resuming initiation flow with a new instance */
+  setInitiator(): void
+  /** Reconcile (server method) */
+  reconcile(query: Buffer): Buffer
+  /** Reconcile (server method), on the libuv threadpool */
+  reconcileAsync(query: Buffer): Promise<Buffer>
+  /** Reconcile (client method) */
+  reconcileWithIds(query: Buffer): ReconcileWithIds
+  /** Reconcile (client method), on the libuv threadpool */
+  reconcileWithIdsAsync(query: Buffer): Promise<ReconcileWithIds>
+}
+
+/** Base class of the negentropy errors: `code` is the error name (e.g. `NotSealed`) */
+export class NegentropyError extends Error {
+  code: string
+}
+
+/** Invalid items or storage state (e.g. `NotSealed`, `InvalidIdSize`) */
+export class StorageError extends NegentropyError {}
+
+/** Invalid or unsupported message from the other side (e.g. `ParseEndsPrematurely`) */
+export class ProtocolError extends NegentropyError {}
+
+/** Invalid session usage (e.g. `FrameSizeLimitTooSmall`, `AlreadyBuiltInitialMessage`) */
+export class SessionError extends NegentropyError {}
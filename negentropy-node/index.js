@@ -0,0 +1,96 @@
+// Copyright (c) 2023 Yuki Kishimoto
+// Distributed under the MIT software license
+
+'use strict'
+
+const native = require('./negentropy.node')
+
+/** Base class of the negentropy errors: `code` is the error name (e.g. `NotSealed`) */
+class NegentropyError extends Error {
+  constructor(message, code) {
+    super(message)
+    this.name = this.constructor.name
+    this.code = code
+  }
+}
+
+/** Invalid items or storage state (e.g. `NotSealed`, `InvalidIdSize`) */
+class StorageError extends NegentropyError {}
+
+/** Invalid or unsupported message from the other side (e.g. `ParseEndsPrematurely`) */
+class ProtocolError extends NegentropyError {}
+
+/** Invalid session usage (e.g. `FrameSizeLimitTooSmall`, `AlreadyBuiltInitialMessage`) */
+class SessionError extends NegentropyError {}
+
+const ERRORS = {
+  IdTooBig: StorageError,
+  InvalidIdSize: StorageError,
+  InvalidHex: StorageError,
+  NotSealed: StorageError,
+  AlreadySealed: StorageError,
+  BadRange: StorageError,
+  UnsortedItems: StorageError,
+  Snapshot: StorageError,
+  Storage: StorageError,
+  UnexpectedMode: ProtocolError,
+  ParseEndsPrematurely: ProtocolError,
+  ProtocolVersionNotFound: ProtocolError,
+  InvalidProtocolVersion: ProtocolError,
+  UnsupportedProtocolVersion: ProtocolError,
+  TryFromSlice: ProtocolError,
+  InvalidIdPrefixLength: ProtocolError,
+  FrameSizeLimitTooSmall: SessionError,
+  AlreadyBuiltInitialMessage: SessionError,
+  NoSupportedVersions: SessionError,
+  Initiator: SessionError,
+  NonInitiator: SessionError,
+}
+
+function mapError(e) {
+  const ErrorClass = e && ERRORS[e.code]
+  return ErrorClass ? new ErrorClass(e.message, e.code) : e
+}
+
+function wrap(method) {
+  return function (...args) {
+    try {
+      const res = method.apply(this, args)
+      return res instanceof Promise
+        ? res.catch((e) => {
+            throw mapError(e)
+          })
+        : res
+    } catch (e) {
+      throw mapError(e)
+    }
+  }
+}
+
+for (const Class of [native.NegentropyStorageVector, native.Negentropy]) {
+  for (const name of Object.getOwnPropertyNames(Class.prototype)) {
+    const method = Class.prototype[name]
+    if (name !== 'constructor' && typeof method === 'function') {
+      Class.prototype[name] = wrap(method)
+    }
+  }
+}
+
+class Negentropy extends native.Negentropy {
+  constructor(storage, frameSizeLimit) {
+    try {
+      super(storage, frameSizeLimit)
+    } catch (e) {
+      throw mapError(e)
+    }
+  }
+}
+
+module.exports = {
+  NegentropyStorageVector: native.NegentropyStorageVector,
+  Negentropy,
+  NegentropyError,
+  StorageError,
+  ProtocolError,
+  SessionError,
+}
@@ -0,0 +1,322 @@
+// Copyright (c) 2023 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! Node.js bindings of the negentropy set-reconciliation protocol.
+//!
+//! Errors are thrown with the `negentropy` error name as `code` (e.g. `NotSealed`):
+//! `index.js` maps them to the JS error classes.
+
+#![deny(clippy::all)]
+
+use std::sync::{Arc, Mutex};
+
+use napi::bindgen_prelude::{AsyncTask, Buffer};
+use napi::{Env, Error, Status, Task};
+use napi_derive::napi;
+use negentropy::{Id, NegentropyStorageBase};
+
+type Result<T, E = Error<String>> = std::result::Result<T, E>;
+
+/// Name of the error, used as JS `code`
+fn code(e: &negentropy::Error) -> &'static str {
+    match e {
+        negentropy::Error::IdTooBig => "IdTooBig",
+        negentropy::Error::InvalidIdSize => "InvalidIdSize",
+        negentropy::Error::InvalidHex => "InvalidHex",
+        negentropy::Error::FrameSizeLimitTooSmall => "FrameSizeLimitTooSmall",
+        negentropy::Error::NotSealed => "NotSealed",
+        negentropy::Error::AlreadySealed => "AlreadySealed",
+        negentropy::Error::AlreadyBuiltInitialMessage => "AlreadyBuiltInitialMessage",
+        negentropy::Error::Initiator => "Initiator",
+        negentropy::Error::NonInitiator => "NonInitiator",
+        negentropy::Error::UnexpectedMode(..) => "UnexpectedMode",
+        negentropy::Error::ParseEndsPrematurely => "ParseEndsPrematurely",
+        negentropy::Error::ProtocolVersionNotFound => "ProtocolVersionNotFound",
+        negentropy::Error::InvalidProtocolVersion => "InvalidProtocolVersion",
+        negentropy::Error::UnsupportedProtocolVersion => "UnsupportedProtocolVersion",
+        negentropy::Error::NoSupportedVersions => "NoSupportedVersions",
+        negentropy::Error::TryFromSlice => "TryFromSlice",
+        negentropy::Error::BadRange => "BadRange",
+        negentropy::Error::InvalidIdPrefixLength => "InvalidIdPrefixLength",
+        negentropy::Error::UnsortedItems => "UnsortedItems",
+        negentropy::Error::Snapshot(..) => "Snapshot",
+        negentropy::Error::Storage(..) => "Storage",
+    }
+}
+
+fn into_err(e: negentropy::Error) -> Error<String> {
+    Error::new(String::from(code(&e)), e.to_string())
+}
+
+fn lock_err<T>(e: std::sync::PoisonError<T>) -> Error<String> {
+    Error::new(String::from("Poisoned"), e.to_string())
+}
+
+type Session = negentropy::Negentropy<'static, negentropy::NegentropyStorageVector>;
+
+/// `Number.MAX_SAFE_INTEGER`: greater JS numbers aren't exact integers
+const MAX_SAFE_INTEGER: i64 = (1 << 53) - 1;
+
+/// Storage
+#[napi]
+pub struct NegentropyStorageVector {
+    inner: Arc<negentropy::NegentropyStorageVector>,
+}
+
+#[napi]
+impl NegentropyStorageVector {
+    /// Create new, empty storage
+    #[napi(constructor)]
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(negentropy::NegentropyStorageVector::new()),
+        }
+    }
+
+    /// Insert item
+    ///
+    /// `created_at` must be an integer between `0` and `Number.MAX_SAFE_INTEGER` (`2^53 - 1`).
+    #[napi]
+    pub fn insert(&mut self, created_at: i64, id: Buffer) -> Result<()> {
+        if !(0..=MAX_SAFE_INTEGER).contains(&created_at) {
+            return Err(Error::new(
+                String::from(Status::InvalidArg.as_ref()),
+                format!("createdAt out of range: {}", created_at),
+            ));
+        }
+        let created_at: u64 = created_at as u64;
+        let id: Id = Id::from_slice(&id).map_err(into_err)?;
+        Arc::make_mut(&mut self.inner)
+            .insert(created_at, id)
+            .map_err(into_err)
+    }
+
+    /// Seal
+    #[napi]
+    pub fn seal(&mut self) -> Result<()> {
+        Arc::make_mut(&mut self.inner).seal().map_err(into_err)
+    }
+
+    /// Unseal
+    #[napi]
+    pub fn unseal(&mut self) -> Result<()> {
+        Arc::make_mut(&mut self.inner).unseal().map_err(into_err)
+    }
+
+    /// Number of items
+    #[napi]
+    pub fn size(&self) -> Result<u32> {
+        let size: usize = self.inner.size().map_err(into_err)?;
+        u32::try_from(size)
+            .map_err(|e| Error::new(String::from(Status::GenericFailure.as_ref()), e.to_string()))
+    }
+}
+
+/// Result of `reconcileWithIds`
+#[napi(object)]
+pub struct ReconcileWithIds {
+    /// IDs we have and the other side needs
+    pub have_ids: Vec<Buffer>,
+    /// IDs we need from the other side
+    pub need_ids: Vec<Buffer>,
+    /// Next message, or `null` when the reconciliation is complete
+    pub output: Option<Buffer>,
+}
+
+#[doc(hidden)]
+pub struct ReconcileOutput {
+    have_ids: Vec<Id>,
+    need_ids: Vec<Id>,
+    output: Option<Vec<u8>>,
+}
+
+impl From<ReconcileOutput> for ReconcileWithIds {
+    fn from(res: ReconcileOutput) -> Self {
+        Self {
+            have_ids: res
+                .have_ids
+                .iter()
+                .map(|id| id.as_bytes().to_vec().into())
+                .collect(),
+            need_ids: res
+                .need_ids
+                .iter()
+                .map(|id| id.as_bytes().to_vec().into())
+                .collect(),
+            output: res.output.map(Buffer::from),
+        }
+    }
+}
+
+fn reconcile(session: &Mutex<Session>, query: &[u8]) -> Result<Vec<u8>> {
+    let mut session = session.lock().map_err(lock_err)?;
+    session.reconcile(query).map_err(into_err)
+}
+
+fn reconcile_with_ids(session: &Mutex<Session>, query: &[u8]) -> Result<ReconcileOutput> {
+    let mut session = session.lock().map_err(lock_err)?;
+    let mut have_ids: Vec<Id> = Vec::new();
+    let mut need_ids: Vec<Id> = Vec::new();
+    let output: Option<Vec<u8>> = session
+        .reconcile_with_ids(query, &mut have_ids, &mut need_ids)
+        .map_err(into_err)?;
+    Ok(ReconcileOutput {
+        have_ids,
+        need_ids,
+        output,
+    })
+}
+
+/// Negentropy session
+#[napi]
+pub struct Negentropy {
+    inner: Arc<Mutex<Session>>,
+}
+
+#[napi]
+impl Negentropy {
+    /// Create new negentropy instance
+    ///
+    /// The storage must be sealed: the session keeps reading the items sealed when it was created,
+    /// even if the storage is later modified.
+    ///
+    /// Frame size limit must be `equal to 0` or `greater than 4096`
+    #[napi(constructor)]
+    pub fn new(storage: &NegentropyStorageVector, frame_size_limit: Option<u32>) -> Result<Self> {
+        storage.inner.size().map_err(into_err)?;
+        let session: Session = negentropy::Negentropy::shared(
+            Arc::clone(&storage.inner),
+            frame_size_limit.unwrap_or_default() as u64,
+        )
+        .map_err(into_err)?;
+        Ok(Self {
+            inner: Arc::new(Mutex::new(session)),
+        })
+    }
+
+    /// Initiate reconciliation set
+    #[napi]
+    pub fn initiate(&self) -> Result<Buffer> {
+        let mut session = self.inner.lock().map_err(lock_err)?;
+        Ok(session.initiate().map_err(into_err)?.into())
+    }
+
+    /// Whether this instance is the initiator
+    #[napi]
+    pub fn is_initiator(&self) -> Result<bool> {
+        let session = self.inner.lock().map_err(lock_err)?;
+        Ok(session.is_initiator())
+    }
+
+    /// Set initiator: for resuming initiation flow with a new instance
+    #[napi]
+    pub fn set_initiator(&self) -> Result<()> {
+        let mut session = self.inner.lock().map_err(lock_err)?;
+        session.set_initiator();
+        Ok(())
+    }
+
+    /// Reconcile (server method)
+    #[napi]
+    pub fn reconcile(&self, query: Buffer) -> Result<Buffer> {
+        Ok(reconcile(&self.inner, &query)?.into())
+    }
+
+    /// Reconcile (server method), on the libuv threadpool
+    #[napi(ts_return_type = "Promise<Buffer>")]
+    pub fn reconcile_async(&self, query: Buffer) -> AsyncTask<ReconcileTask> {
+        AsyncTask::new(ReconcileTask {
+            session: Arc::clone(&self.inner),
+            query: query.to_vec(),
+            error: None,
+        })
+    }
+
+    /// Reconcile (client method)
+    #[napi]
+    pub fn reconcile_with_ids(&self, query: Buffer) -> Result<ReconcileWithIds> {
+        Ok(reconcile_with_ids(&self.inner, &query)?.into())
+    }
+
+    /// Reconcile (client method), on the libuv threadpool
+    #[napi(ts_return_type = "Promise<ReconcileWithIds>")]
+    pub fn reconcile_with_ids_async(&self, query: Buffer) -> AsyncTask<ReconcileWithIdsTask> {
+        AsyncTask::new(ReconcileWithIdsTask {
+            session: Arc::clone(&self.inner),
+            query: query.to_vec(),
+            error: None,
+        })
+    }
+}
+
+/// Keep the error code of a failed task (the threadpool only carries a `Status`)
+fn compute<T>(error: &mut Option<Error<String>>, res: Result<T>) -> napi::Result<T> {
+    res.map_err(|e| {
+        let err = Error::new(Status::GenericFailure, e.reason.clone());
+        *error = Some(e);
+        err
+    })
+}
+
+fn reject<T>(env: Env, error: &mut Option<Error<String>>, err: Error) -> napi::Result<T> {
+    match error.take() {
+        Some(e) => {
+            let mut obj = env.create_error(Error::new(Status::GenericFailure, e.reason))?;
+            obj.set_named_property("code", env.create_string(&e.status)?)?;
+            Err(Error::from(obj.into_unknown()))
+        }
+        None => Err(err),
+    }
+}
+
+#[doc(hidden)]
+pub struct ReconcileTask {
+    session: Arc<Mutex<Session>>,
+    query: Vec<u8>,
+    error: Option<Error<String>>,
+}
+
+impl Task for ReconcileTask {
+    type Output = Vec<u8>;
+    type JsValue = Buffer;
+
+    fn compute(&mut self) -> napi::Result<Self::Output> {
+        compute(&mut self.error, reconcile(&self.session, &self.query))
+    }
+
+    fn resolve(&mut self, _env: Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+        Ok(output.into())
+    }
+
+    fn reject(&mut self, env: Env, err: Error) -> napi::Result<Self::JsValue> {
+        reject(env, &mut self.error, err)
+    }
+}
+
+#[doc(hidden)]
+pub struct ReconcileWithIdsTask {
+    session: Arc<Mutex<Session>>,
+    query: Vec<u8>,
+    error: Option<Error<String>>,
+}
+
+impl Task for ReconcileWithIdsTask {
+    type Output = ReconcileOutput;
+    type JsValue = ReconcileWithIds;
+
+    fn compute(&mut self) -> napi::Result<Self::Output> {
+        compute(
+            &mut self.error,
+            reconcile_with_ids(&self.session, &self.query),
+        )
+    }
+
+    fn resolve(&mut self, _env: Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+        Ok(output.into())
+    }
+
+    fn reject(&mut self, env: Env, err: Error) -> napi::Result<Self::JsValue> {
+        reject(env, &mut self.error, err)
+    }
+}
@@ -0,0 +1,149 @@
+// Copyright (c) 2023 Yuki Kishimoto
+// Distributed under the MIT software license
+
+'use strict'
+
+const assert = require('node:assert')
+const { test } = require('node:test')
+
+const {
+  Negentropy,
+  NegentropyStorageVector,
+  ProtocolError,
+  SessionError,
+  StorageError,
+} = require('..')
+
+function id(byte) {
+  return Buffer.alloc(32, byte)
+}
+
+function storage(items) {
+  const storage = new NegentropyStorageVector()
+  for (const [createdAt, byte] of items) {
+    storage.insert(createdAt, id(byte))
+  }
+  storage.seal()
+  return storage
+}
+
+function range(begin, end) {
+  const items = []
+  for (let i = begin; i < end; i++) {
+    items.push([i, i])
+  }
+  return items
+}
+
+function sorted(ids) {
+  return ids.map((id) => id.toString('hex')).sort()
+}
+
+async function sync(client, relay, { async }) {
+  const haveIds = []
+  const needIds = []
+
+  let msg = client.initiate()
+  while (msg !== null && msg !== undefined) {
+    const response = async ? await relay.reconcileAsync(msg) : relay.reconcile(msg)
+    const res = async
+      ? await client.reconcileWithIdsAsync(response)
+      : client.reconcileWithIds(response)
+    haveIds.push(...res.haveIds)
+    needIds.push(...res.needIds)
+    msg = res.output
+  }
+
+  return { haveIds, needIds }
+}
+
+// Same vectors as `test_reconciliation_set` in the `negentropy` crate
+test('reconciliation set', async () => {
+  for (const async of [false, true]) {
+    const client = new Negentropy(
+      storage([
+        [0, 0xaa],
+        [1, 0xbb],
+      ]),
+    )
+    const relay = new Negentropy(
+      storage([
+        [0, 0xaa],
+        [2, 0xcc],
+        [3, 0x11],
+        [5, 0x22],
+        [10, 0x33],
+      ]),
+    )
+
+    const { haveIds, needIds } = await sync(client, relay, { async })
+    assert.deepStrictEqual(sorted(haveIds), sorted([id(0xbb)]))
+    assert.deepStrictEqual(sorted(needIds), sorted([id(0x11), id(0x22), id(0x33), id(0xcc)]))
+  }
+})
+
+test('reconciliation with frame size limit', async () => {
+  const client = new Negentropy(storage(range(0, 200)), 4096 + 1)
+  const relay = new Negentropy(storage(range(100, 256)), 4096 + 1)
+
+  const { haveIds, needIds } = await sync(client, relay, { async: true })
+  assert.deepStrictEqual(sorted(haveIds), sorted(range(0, 100).map(([, b]) => id(b))))
+  assert.deepStrictEqual(sorted(needIds), sorted(range(200, 256).map(([, b]) => id(b))))
+})
+
+test('storage', () => {
+  const vector = new NegentropyStorageVector()
+  vector.insert(2, id(2))
+  vector.insert(1, id(1))
+  vector.insert(1, id(1))
+  assert.throws(() => vector.size(), StorageError)
+
+  vector.seal()
+  assert.strictEqual(vector.size(), 2)
+
+  // Sessions keep reading the sealed items
+  const session = new Negentropy(vector)
+  vector.unseal()
+  vector.insert(3, id(3))
+  vector.seal()
+  assert.strictEqual(vector.size(), 3)
+  assert.strictEqual(session.isInitiator(), false)
+  session.initiate()
+  assert.strictEqual(session.isInitiator(), true)
+})
+
+test('errors', async () => {
+  const vector = new NegentropyStorageVector()
+  assert.throws(() => vector.insert(0, Buffer.alloc(31)), {
+    name: 'StorageError',
+    code: 'InvalidIdSize',
+  })
+  assert.throws(() => vector.insert(-1, id(0)), { code: 'InvalidArg' })
+  assert.throws(() => vector.insert(2 ** 53, id(0)), { code: 'InvalidArg' })
+  vector.insert(Number.MAX_SAFE_INTEGER, id(0))
+  assert.throws(() => new Negentropy(vector), { name: 'StorageError', code: 'NotSealed' })
+
+  vector.seal()
+  assert.throws(() => vector.seal(), { name: 'StorageError', code: 'AlreadySealed' })
+  assert.throws(() => new Negentropy(vector, 100), SessionError)
+
+  const client = new Negentropy(vector)
+  client.initiate()
+  assert.throws(() => client.initiate(), {
+    name: 'SessionError',
+    code: 'AlreadyBuiltInitialMessage',
+  })
+
+  const relay = new Negentropy(vector)
+  const query = Buffer.from([0x61, 0x00, 0x00, 0x05])
+  assert.throws(() => relay.reconcile(query), ProtocolError)
+  await assert.rejects(relay.reconcileAsync(query), {
+    name: 'ProtocolError',
+    code: 'UnexpectedMode',
+    message: 'Unexpected mode: 5',
+  })
+  await assert.rejects(relay.reconcileWithIdsAsync(Buffer.from([0x61])), {
+    name: 'SessionError',
+    code: 'NonInitiator',
+  })
+})
@@ -0,0 +1,172 @@
+// Copyright (c) 2023 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! WebAssembly bindings of the negentropy set-reconciliation protocol.
+//!
+//! IDs are 32-byte `Uint8Array`s and timestamps are `bigint`s (`BigUint64Array` for bulk loading).
+//! Errors are thrown as JS `Error`s.
+
+#![allow(clippy::new_without_default)]
+#![warn(missing_docs)]
+
+use std::sync::Arc;
+
+use js_sys::{Array, Uint8Array};
+use negentropy::{Id, Item, NegentropyStorageBase, ID_SIZE};
+use wasm_bindgen::prelude::*;
+
+type Result<T, E = JsError> = std::result::Result<T, E>;
+
+fn into_err(e: negentropy::Error) -> JsError {
+    JsError::new(&e.to_string())
+}
+
+fn ids_to_array(ids: Vec<Id>) -> Array {
+    ids.iter()
+        .map(|id| Uint8Array::from(id.as_bytes().as_slice()))
+        .collect()
+}
+
+/// Storage
+#[wasm_bindgen(js_name = NegentropyStorageVector)]
+pub struct NegentropyStorageVector {
+    inner: Arc<negentropy::NegentropyStorageVector>,
+}
+
+#[wasm_bindgen(js_class = NegentropyStorageVector)]
+impl NegentropyStorageVector {
+    /// Create new, empty storage
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(negentropy::NegentropyStorageVector::new()),
+        }
+    }
+
+    /// Create new sealed storage from the timestamps and the concatenated 32-byte IDs
+    ///
+    /// Items don't need to be sorted.
+    #[wasm_bindgen(js_name = fromItems)]
+    pub fn from_items(timestamps: &[u64], ids: &[u8]) -> Result<NegentropyStorageVector> {
+        if ids.len() != timestamps.len() * ID_SIZE {
+            return Err(into_err(negentropy::Error::InvalidIdSize));
+        }
+
+        let items: Vec<Item> = timestamps
+            .iter()
+            .zip(ids.chunks_exact(ID_SIZE))
+            .map(|(timestamp, id)| Ok(Item::with_timestamp_and_id(*timestamp, Id::from_slice(id)?)))
+            .collect::<Result<_, negentropy::Error>>()
+            .map_err(into_err)?;
+
+        Ok(Self {
+            inner: Arc::new(items.into_iter().collect()),
+        })
+    }
+
+    /// Insert item
+    pub fn insert(&mut self, timestamp: u64, id: &[u8]) -> Result<()> {
+        let id: Id = Id::from_slice(id).map_err(into_err)?;
+        Arc::make_mut(&mut self.inner)
+            .insert(timestamp, id)
+            .map_err(into_err)
+    }
+
+    /// Seal
+    pub fn seal(&mut self) -> Result<()> {
+        Arc::make_mut(&mut self.inner).seal().map_err(into_err)
+    }
+
+    /// Unseal
+    pub fn unseal(&mut self) -> Result<()> {
+        Arc::make_mut(&mut self.inner).unseal().map_err(into_err)
+    }
+
+    /// Number of items
+    pub fn size(&self) -> Result<usize> {
+        self.inner.size().map_err(into_err)
+    }
+}
+
+/// Result of [`Negentropy::reconcile_with_ids`]
+#[wasm_bindgen(getter_with_clone)]
+pub struct ReconcileWithIds {
+    /// IDs we have and the other side needs (array of `Uint8Array`)
+    #[wasm_bindgen(js_name = haveIds)]
+    pub have_ids: Array,
+    /// IDs we need from the other side (array of `Uint8Array`)
+    #[wasm_bindgen(js_name = needIds)]
+    pub need_ids: Array,
+    /// Next message, or `undefined` when the reconciliation is complete
+    pub output: Option<Uint8Array>,
+}
+
+/// Negentropy session
+#[wasm_bindgen]
+pub struct Negentropy {
+    inner: negentropy::Negentropy<'static, negentropy::NegentropyStorageVector>,
+}
+
+#[wasm_bindgen]
+impl Negentropy {
+    /// Create new negentropy instance
+    ///
+    /// The storage must be sealed: the session keeps reading the items sealed when it was created,
+    /// even if the storage is later modified.
+    ///
+    /// Frame size limit must be `equal to 0` or `greater than 4096`
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        storage: &NegentropyStorageVector,
+        frame_size_limit: Option<u64>,
+    ) -> Result<Negentropy> {
+        storage.inner.size().map_err(into_err)?;
+        Ok(Self {
+            inner: negentropy::Negentropy::shared(
+                Arc::clone(&storage.inner),
+                frame_size_limit.unwrap_or_default(),
+            )
+            .map_err(into_err)?,
+        })
+    }
+
+    /// Initiate reconciliation set
+    pub fn initiate(&mut self) -> Result<Uint8Array> {
+        let msg: Vec<u8> = self.inner.initiate().map_err(into_err)?;
+        Ok(Uint8Array::from(msg.as_slice()))
+    }
+
+    /// Whether this instance is the initiator
+    #[wasm_bindgen(js_name = isInitiator)]
+    pub fn is_initiator(&self) -> bool {
+        self.inner.is_initiator()
+    }
+
+    /// Set initiator: for resuming initiation flow with a new instance
+    #[wasm_bindgen(js_name = setInitiator)]
+    pub fn set_initiator(&mut self) {
+        self.inner.set_initiator();
+    }
+
+    /// Reconcile (server method)
+    pub fn reconcile(&mut self, query: &[u8]) -> Result<Uint8Array> {
+        let msg: Vec<u8> = self.inner.reconcile(query).map_err(into_err)?;
+        Ok(Uint8Array::from(msg.as_slice()))
+    }
+
+    /// Reconcile (client method)
+    #[wasm_bindgen(js_name = reconcileWithIds)]
+    pub fn reconcile_with_ids(&mut self, query: &[u8]) -> Result<ReconcileWithIds> {
+        let mut have_ids: Vec<Id> = Vec::new();
+        let mut need_ids: Vec<Id> = Vec::new();
+        let output: Option<Vec<u8>> = self
+            .inner
+            .reconcile_with_ids(query, &mut have_ids, &mut need_ids)
+            .map_err(into_err)?;
+        Ok(ReconcileWithIds {
+            have_ids: ids_to_array(have_ids),
+            need_ids: ids_to_array(need_ids),
+            output: output.map(|msg| Uint8Array::from(msg.as_slice())),
+        })
+    }
+}
@@ -0,0 +1,80 @@
+// Copyright (c) 2023 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! Run with `wasm-pack test --node` (or `cargo test` with `wasm-bindgen-test-runner` installed)
+
+#![cfg(target_arch = "wasm32")]
+
+use js_sys::Uint8Array;
+use negentropy_wasm::{Negentropy, NegentropyStorageVector};
+use wasm_bindgen_test::*;
+
+fn storage(range: std::ops::Range<u8>) -> NegentropyStorageVector {
+    let timestamps: Vec<u64> = range.clone().map(u64::from).collect();
+    let ids: Vec<u8> = range.flat_map(|i| [i; 32]).collect();
+    NegentropyStorageVector::from_items(&timestamps, &ids).unwrap()
+}
+
+fn first_bytes(ids: &js_sys::Array) -> Vec<u8> {
+    let mut bytes: Vec<u8> = ids
+        .iter()
+        .map(|id| {
+            let id: Vec<u8> = Uint8Array::from(id).to_vec();
+            assert_eq!(id.len(), 32);
+            id[0]
+        })
+        .collect();
+    bytes.sort_unstable();
+    bytes
+}
+
+#[wasm_bindgen_test]
+fn test_reconciliation() {
+    let mut client = Negentropy::new(&storage(0..100), None).unwrap();
+    let mut relay = Negentropy::new(&storage(50..200), None).unwrap();
+
+    let mut have_ids = js_sys::Array::new();
+    let mut need_ids = js_sys::Array::new();
+    let mut msg: Uint8Array = client.initiate().unwrap();
+    loop {
+        let response: Uint8Array = relay.reconcile(&msg.to_vec()).unwrap();
+        let res = client.reconcile_with_ids(&response.to_vec()).unwrap();
+        have_ids = have_ids.concat(&res.have_ids);
+        need_ids = need_ids.concat(&res.need_ids);
+        match res.output {
+            Some(next) => msg = next,
+            None => break,
+        }
+    }
+
+    assert_eq!(first_bytes(&have_ids), (0..50).collect::<Vec<u8>>());
+    assert_eq!(first_bytes(&need_ids), (100..200).collect::<Vec<u8>>());
+}
+
+#[wasm_bindgen_test]
+fn test_storage() {
+    let mut storage = NegentropyStorageVector::new();
+    storage.insert(2, &[2; 32]).unwrap();
+    storage.insert(1, &[1; 32]).unwrap();
+    storage.insert(1, &[1; 32]).unwrap();
+    assert!(storage.size().is_err());
+    assert!(Negentropy::new(&storage, None).is_err());
+
+    storage.seal().unwrap();
+    assert_eq!(storage.size().unwrap(), 2);
+
+    let mut session = Negentropy::new(&storage, None).unwrap();
+    assert!(!session.is_initiator());
+    session.initiate().unwrap();
+    assert!(session.is_initiator());
+}
+
+#[wasm_bindgen_test]
+fn test_errors() {
+    assert!(NegentropyStorageVector::from_items(&[1, 2], &[0; 32]).is_err());
+    assert!(NegentropyStorageVector::new().insert(1, &[0; 31]).is_err());
+    assert!(Negentropy::new(&storage(0..10), Some(100)).is_err());
+
+    let mut relay = Negentropy::new(&storage(0..10), None).unwrap();
+    assert!(relay.reconcile(&[0x61, 0x00, 0x00, 0x05]).is_err());
+}
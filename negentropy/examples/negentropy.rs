@@ -1,7 +1,7 @@
 // Copyright (c) 2023 Yuki Kishimoto
 // Distributed under the MIT software license
 
-use negentropy::{Id, Negentropy, NegentropyStorageVector};
+use negentropy::{Id, NegentropyClient, NegentropyServer, NegentropyStorageVector};
 
 fn main() {
     // Client
@@ -19,7 +19,7 @@ fn main() {
         )
         .unwrap();
     storage_client.seal().unwrap();
-    let mut client = Negentropy::borrowed(&storage_client, 0).unwrap();
+    let mut client = NegentropyClient::borrowed(&storage_client, 0).unwrap();
     let init_output = client.initiate().unwrap();
     println!("Initiator Output: {:x?}", init_output.clone());
 
@@ -56,7 +56,7 @@ fn main() {
         )
         .unwrap();
     storage_relay.seal().unwrap();
-    let mut relay = Negentropy::borrowed(&storage_relay, 0).unwrap();
+    let mut relay = NegentropyServer::borrowed(&storage_relay, 0).unwrap();
     let reconcile_output = relay.reconcile(&init_output).unwrap();
     println!("Reconcile Output: {:x?}", reconcile_output.clone());
 
@@ -64,22 +64,22 @@ fn main() {
     let mut have_ids = Vec::new();
     let mut need_ids = Vec::new();
     client
-        .reconcile_with_ids(&reconcile_output, &mut have_ids, &mut need_ids)
+        .reconcile(&reconcile_output, &mut have_ids, &mut need_ids)
         .unwrap();
     println!(
         "Have IDs: {}",
         have_ids
             .into_iter()
-            .map(|b| format!("{:x?}", b))
+            .map(|id| id.to_string())
             .collect::<Vec<_>>()
-            .join("")
+            .join(", ")
     );
     println!(
         "Need IDs: {}",
         need_ids
             .into_iter()
-            .map(|b| format!("{:x?}", b))
+            .map(|id| id.to_string())
             .collect::<Vec<_>>()
-            .join("")
+            .join(", ")
     );
 }
@@ -9,6 +9,33 @@ use std::io::{self, BufRead};
 
 use negentropy::{Id, Negentropy, NegentropyStorageVector};
 
+const HEX_CHARS: &[u8; 16] = b"0123456789abcdef";
+
+/// Encode as lowercase hex
+fn to_hex(bytes: &[u8]) -> String {
+    let mut hex: String = String::with_capacity(bytes.len() * 2);
+    for byte in bytes.iter() {
+        hex.push(HEX_CHARS[(byte >> 4) as usize] as char);
+        hex.push(HEX_CHARS[(byte & 0x0f) as usize] as char);
+    }
+    hex
+}
+
+/// Decode hex, `None` if malformed
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    hex.as_bytes()
+        .chunks(2)
+        .map(|chunk| match chunk {
+            [high, low] => {
+                let high: u32 = (*high as char).to_digit(16)?;
+                let low: u32 = (*low as char).to_digit(16)?;
+                Some((high << 4 | low) as u8)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
 fn main() {
     let frame_size_limit_env_var = env::var("FRAMESIZELIMIT");
     let frame_size_limit = if let Ok(frame_size_limit) = frame_size_limit_env_var {
@@ -25,11 +52,8 @@ fn main() {
 
         if items[0] == "item" {
             let created = items[1].parse::<u64>().unwrap();
-            let id = items[2];
-            let bytes = hex::decode(id).unwrap();
-            storage
-                .insert(created, Id::from_slice(&bytes).unwrap())
-                .unwrap();
+            let id = Id::from_hex(items[2]).unwrap();
+            storage.insert(created, id).unwrap();
         } else if items[0] == "seal" {
             storage.seal().unwrap();
             break;
@@ -46,10 +70,10 @@ fn main() {
 
         if items[0] == "initiate" {
             let q = ne.initiate().unwrap();
-            if frame_size_limit > 0 && q.len() / 2 > frame_size_limit {
+            if frame_size_limit > 0 && q.len() > frame_size_limit {
                 panic!("frame_size_limit exceeded");
             }
-            println!("msg,{}", hex::encode(q));
+            println!("msg,{}", to_hex(&q));
         } else if items[0] == "msg" {
             let mut q = String::new();
 
@@ -60,28 +84,28 @@ fn main() {
             if ne.is_initiator() {
                 let mut have_ids = Vec::new();
                 let mut need_ids = Vec::new();
-                let bytes = hex::decode(q).unwrap();
+                let bytes = from_hex(&q).unwrap();
                 let resp = ne
                     .reconcile_with_ids(&bytes, &mut have_ids, &mut need_ids)
                     .unwrap();
 
                 for id in have_ids.into_iter() {
-                    println!("have,{}", hex::encode(id.as_bytes()));
+                    println!("have,{}", id);
                 }
                 for id in need_ids.into_iter() {
-                    println!("need,{}", hex::encode(id.as_bytes()));
+                    println!("need,{}", id);
                 }
 
                 if let Some(resp) = resp {
-                    q = hex::encode(resp);
+                    q = to_hex(&resp);
                 } else {
                     println!("done");
                     continue;
                 }
             } else {
-                let bytes = hex::decode(q).unwrap();
+                let bytes = from_hex(&q).unwrap();
                 let out = ne.reconcile(&bytes).unwrap();
-                q = hex::encode(out);
+                q = to_hex(&out);
             }
 
             if frame_size_limit > 0 && q.len() / 2 > frame_size_limit {
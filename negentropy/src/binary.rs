@@ -0,0 +1,870 @@
+// Copyright (c) 2023 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! Binary snapshot format
+//!
+//! Versioned and checksummed format of a sealed storage. Integers are little-endian.
+//!
+//! ```text
+//! magic         4 bytes   "NEGS"
+//! version       u8        1
+//! flags         u8        bit 0: prefix accumulators
+//! ID size       u16       N
+//! count         u64
+//! records       count * (<timestamp u64> <id N bytes>), sorted by (timestamp, id), without duplicates
+//! accumulators  (count + 1) * N bytes, if flagged: entry i is the sum of the IDs of the first i records
+//! checksum      u32       CRC-32 (IEEE) of all the preceding bytes
+//! ```
+//!
+//! Load it with [`NegentropyStorageVector::from_bytes`], or read it in place with [`NegentropyStorageBytes`].
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt;
+
+use crate::constants::ID_SIZE;
+use crate::storage::NegentropyStorageVector;
+use crate::types::Bound;
+use crate::{Accumulator, Error, Id, Item, NegentropyStorageBase};
+
+/// Snapshot magic bytes
+pub const SNAPSHOT_MAGIC: [u8; 4] = *b"NEGS";
+/// Snapshot format version
+pub const SNAPSHOT_VERSION: u8 = 1;
+/// Snapshot header size
+pub const SNAPSHOT_HEADER_SIZE: usize = 16;
+
+const FLAG_PREFIX_ACCUMULATORS: u8 = 0b0000_0001;
+const TIMESTAMP_SIZE: usize = 8;
+const CHECKSUM_SIZE: usize = 4;
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Snapshot error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// Bad magic bytes
+    BadMagic,
+    /// Unsupported format version
+    UnsupportedVersion(u8),
+    /// Unknown flags
+    UnknownFlags(u8),
+    /// ID size doesn't match the storage one
+    IdSizeMismatch(u16),
+    /// Snapshot ends prematurely
+    Truncated,
+    /// Records not sorted or not unique
+    UnsortedItems,
+    /// Prefix accumulators don't match the records
+    AccumulatorMismatch,
+    /// Checksum mismatch
+    ChecksumMismatch,
+    /// Trailing bytes after the checksum
+    TrailingBytes,
+    /// I/O error
+    #[cfg(feature = "std")]
+    Io(std::io::ErrorKind),
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SnapshotError {}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadMagic => write!(f, "bad magic bytes"),
+            Self::UnsupportedVersion(v) => write!(f, "unsupported format version: {}", v),
+            Self::UnknownFlags(flags) => write!(f, "unknown flags: {:#010b}", flags),
+            Self::IdSizeMismatch(size) => write!(f, "ID size mismatch: {}", size),
+            Self::Truncated => write!(f, "snapshot ends prematurely"),
+            Self::UnsortedItems => write!(f, "records not sorted or not unique"),
+            Self::AccumulatorMismatch => write!(f, "prefix accumulators mismatch"),
+            Self::ChecksumMismatch => write!(f, "checksum mismatch"),
+            Self::TrailingBytes => write!(f, "trailing bytes"),
+            #[cfg(feature = "std")]
+            Self::Io(kind) => write!(f, "I/O error: {:?}", kind),
+        }
+    }
+}
+
+/// Snapshot header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotHeader {
+    /// Number of records
+    pub count: u64,
+    /// Whether the prefix accumulators follow the records
+    pub prefix_accumulators: bool,
+}
+
+impl SnapshotHeader {
+    /// Parse the header of a snapshot of `N`-byte IDs
+    pub fn parse<const N: usize>(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < SNAPSHOT_HEADER_SIZE {
+            return Err(SnapshotError::Truncated.into());
+        }
+
+        if bytes[0..4] != SNAPSHOT_MAGIC {
+            return Err(SnapshotError::BadMagic.into());
+        }
+
+        if bytes[4] != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(bytes[4]).into());
+        }
+
+        let flags: u8 = bytes[5];
+        if flags & !FLAG_PREFIX_ACCUMULATORS != 0 {
+            return Err(SnapshotError::UnknownFlags(flags).into());
+        }
+
+        let id_size: u16 = u16::from_le_bytes([bytes[6], bytes[7]]);
+        if id_size as usize != N {
+            return Err(SnapshotError::IdSizeMismatch(id_size).into());
+        }
+
+        let mut count: [u8; 8] = [0u8; 8];
+        count.copy_from_slice(&bytes[8..16]);
+
+        Ok(Self {
+            count: u64::from_le_bytes(count),
+            prefix_accumulators: flags & FLAG_PREFIX_ACCUMULATORS != 0,
+        })
+    }
+
+    /// Size of a record of `N`-byte ID
+    #[inline]
+    pub const fn record_size<const N: usize>() -> usize {
+        TIMESTAMP_SIZE + N
+    }
+
+    /// Total snapshot size, `None` on overflow
+    pub fn snapshot_size<const N: usize>(&self) -> Option<usize> {
+        let count: usize = usize::try_from(self.count).ok()?;
+        let mut size: usize = count.checked_mul(Self::record_size::<N>())?;
+
+        if self.prefix_accumulators {
+            size = size.checked_add(count.checked_add(1)?.checked_mul(N)?)?;
+        }
+
+        size.checked_add(SNAPSHOT_HEADER_SIZE + CHECKSUM_SIZE)
+    }
+
+    fn to_bytes<const N: usize>(self) -> Result<[u8; SNAPSHOT_HEADER_SIZE], Error> {
+        let id_size: u16 = u16::try_from(N).map_err(|_| Error::InvalidIdSize)?;
+
+        let mut bytes: [u8; SNAPSHOT_HEADER_SIZE] = [0u8; SNAPSHOT_HEADER_SIZE];
+        bytes[0..4].copy_from_slice(&SNAPSHOT_MAGIC);
+        bytes[4] = SNAPSHOT_VERSION;
+        bytes[5] = if self.prefix_accumulators {
+            FLAG_PREFIX_ACCUMULATORS
+        } else {
+            0
+        };
+        bytes[6..8].copy_from_slice(&id_size.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.count.to_le_bytes());
+        Ok(bytes)
+    }
+}
+
+impl<const N: usize> NegentropyStorageVector<N> {
+    /// Serialize to the [binary snapshot format](crate::binary), optionally with the prefix accumulators
+    pub fn to_bytes(&self, prefix_accumulators: bool) -> Result<Vec<u8>, Error> {
+        let mut bytes: Vec<u8> = Vec::new();
+        encode(self, prefix_accumulators, &mut |chunk: &[u8]| {
+            bytes.extend_from_slice(chunk);
+            Ok(())
+        })?;
+        Ok(bytes)
+    }
+
+    /// Parse the [binary snapshot format](crate::binary)
+    ///
+    /// The records order, the prefix accumulators (if any) and the checksum are validated.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut source: &[u8] = bytes;
+        let storage: Self = decode(&mut source, Some(bytes.len()))?;
+
+        if !source.is_empty() {
+            return Err(SnapshotError::TrailingBytes.into());
+        }
+
+        Ok(storage)
+    }
+
+    /// Save to the [binary snapshot format](crate::binary), optionally with the prefix accumulators
+    #[cfg(feature = "std")]
+    pub fn save_to<W>(&self, mut writer: W, prefix_accumulators: bool) -> Result<(), Error>
+    where
+        W: std::io::Write,
+    {
+        encode(self, prefix_accumulators, &mut |chunk: &[u8]| {
+            writer.write_all(chunk).map_err(io_error)
+        })?;
+        writer.flush().map_err(io_error)
+    }
+
+    /// Load from the [binary snapshot format](crate::binary)
+    ///
+    /// The records order, the prefix accumulators (if any) and the checksum are validated.
+    /// Nothing is read after the checksum.
+    #[cfg(feature = "std")]
+    pub fn load_from<R>(reader: R) -> Result<Self, Error>
+    where
+        R: std::io::Read,
+    {
+        decode(&mut ReadSource(reader), None)
+    }
+}
+
+/// Read-only storage over the [binary snapshot format](crate::binary), without copying
+///
+/// `B` is any byte container: a `Vec<u8>`, a `&[u8]` or a memory-mapped file.
+/// Items are decoded on access, [`NegentropyStorageBase::find_lower_bound`] is a binary search
+/// and, if the snapshot has the prefix accumulators, fingerprints are computed in `O(1)`.
+#[derive(Debug, Clone)]
+pub struct NegentropyStorageBytes<B, const N: usize = ID_SIZE> {
+    bytes: B,
+    count: usize,
+    prefix_accumulators: bool,
+}
+
+impl<B> NegentropyStorageBytes<B>
+where
+    B: AsRef<[u8]>,
+{
+    /// Create new storage
+    ///
+    /// Only the header and the length are checked: see [`NegentropyStorageBytes::validate`].
+    ///
+    /// For ID sizes other than [`ID_SIZE`] use [`NegentropyStorageBytes::parse`].
+    #[inline]
+    pub fn new(bytes: B) -> Result<Self, Error> {
+        Self::parse(bytes)
+    }
+}
+
+impl<B, const N: usize> NegentropyStorageBytes<B, N>
+where
+    B: AsRef<[u8]>,
+{
+    /// Parse a snapshot of `N`-byte IDs
+    ///
+    /// Only the header and the length are checked: see [`NegentropyStorageBytes::validate`].
+    pub fn parse(bytes: B) -> Result<Self, Error> {
+        let buf: &[u8] = bytes.as_ref();
+        let header: SnapshotHeader = SnapshotHeader::parse::<N>(buf)?;
+
+        let snapshot_size: usize = header
+            .snapshot_size::<N>()
+            .ok_or(SnapshotError::Truncated)?;
+        if buf.len() < snapshot_size {
+            return Err(SnapshotError::Truncated.into());
+        }
+        if buf.len() > snapshot_size {
+            return Err(SnapshotError::TrailingBytes.into());
+        }
+
+        Ok(Self {
+            count: header.count as usize,
+            prefix_accumulators: header.prefix_accumulators,
+            bytes,
+        })
+    }
+
+    /// Validate the records order, the prefix accumulators (if any) and the checksum in `O(n)`
+    pub fn validate(&self) -> Result<(), Error> {
+        let buf: &[u8] = self.bytes.as_ref();
+        let (content, checksum) = buf.split_at(buf.len() - CHECKSUM_SIZE);
+
+        let mut crc: Crc32 = Crc32::new();
+        crc.update(content);
+        let mut expected: [u8; CHECKSUM_SIZE] = [0u8; CHECKSUM_SIZE];
+        expected.copy_from_slice(checksum);
+        if u32::from_le_bytes(expected) != crc.finish() {
+            return Err(SnapshotError::ChecksumMismatch.into());
+        }
+
+        let mut acc: Accumulator<N> = Accumulator::new();
+        for i in 0..self.count {
+            let item: Item<N> = self.item(i);
+
+            if i > 0 && self.item(i - 1) >= item {
+                return Err(SnapshotError::UnsortedItems.into());
+            }
+
+            if self.prefix_accumulators && self.accumulator(i) != acc {
+                return Err(SnapshotError::AccumulatorMismatch.into());
+            }
+
+            acc.add(item.id.as_bytes());
+        }
+
+        if self.prefix_accumulators && self.accumulator(self.count) != acc {
+            return Err(SnapshotError::AccumulatorMismatch.into());
+        }
+
+        Ok(())
+    }
+
+    /// Whether the snapshot has the prefix accumulators
+    #[inline]
+    pub fn has_prefix_accumulators(&self) -> bool {
+        self.prefix_accumulators
+    }
+
+    /// Get the underlying bytes
+    #[inline]
+    pub fn into_inner(self) -> B {
+        self.bytes
+    }
+
+    /// Decode item `i` (must be `< count`)
+    fn item(&self, i: usize) -> Item<N> {
+        let record_size: usize = SnapshotHeader::record_size::<N>();
+        let offset: usize = SNAPSHOT_HEADER_SIZE + i * record_size;
+        let record: &[u8] = &self.bytes.as_ref()[offset..offset + record_size];
+
+        let mut timestamp: [u8; TIMESTAMP_SIZE] = [0u8; TIMESTAMP_SIZE];
+        timestamp.copy_from_slice(&record[..TIMESTAMP_SIZE]);
+        let mut id: [u8; N] = [0u8; N];
+        id.copy_from_slice(&record[TIMESTAMP_SIZE..]);
+
+        Item::with_timestamp_and_id(u64::from_le_bytes(timestamp), Id::from_byte_array(id))
+    }
+
+    /// Decode prefix accumulator `i` (must be `<= count`)
+    fn accumulator(&self, i: usize) -> Accumulator<N> {
+        let offset: usize =
+            SNAPSHOT_HEADER_SIZE + self.count * SnapshotHeader::record_size::<N>() + i * N;
+
+        let mut buf: [u8; N] = [0u8; N];
+        buf.copy_from_slice(&self.bytes.as_ref()[offset..offset + N]);
+        Accumulator::from_bytes(buf)
+    }
+
+    fn check_bounds(&self, begin: usize, end: usize) -> Result<(), Error> {
+        if begin > end || end > self.count {
+            return Err(Error::BadRange);
+        }
+        Ok(())
+    }
+}
+
+impl<B, const N: usize> NegentropyStorageBase<N> for NegentropyStorageBytes<B, N>
+where
+    B: AsRef<[u8]>,
+{
+    fn size(&self) -> Result<usize, Error> {
+        Ok(self.count)
+    }
+
+    fn get_item(&self, i: usize) -> Result<Option<Item<N>>, Error> {
+        if i >= self.count {
+            return Ok(None);
+        }
+
+        Ok(Some(self.item(i)))
+    }
+
+    fn iterate(
+        &self,
+        begin: usize,
+        end: usize,
+        cb: &mut dyn FnMut(Item<N>, usize) -> Result<bool, Error>,
+    ) -> Result<(), Error> {
+        self.check_bounds(begin, end)?;
+
+        for i in begin..end {
+            if !cb(self.item(i), i)? {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn find_lower_bound(&self, mut first: usize, last: usize, value: &Bound<N>) -> usize {
+        let mut count: usize = last - first;
+
+        while count > 0 {
+            let step: usize = count / 2;
+            let it: usize = first + step;
+
+            if self.item(it) < value.item {
+                first = it + 1;
+                count -= step + 1;
+            } else {
+                count = step;
+            }
+        }
+
+        first
+    }
+
+    fn accumulate(&self, begin: usize, end: usize) -> Result<Accumulator<N>, Error> {
+        self.check_bounds(begin, end)?;
+
+        let mut out: Accumulator<N> = Accumulator::new();
+
+        if self.prefix_accumulators {
+            out = self.accumulator(end);
+            out.sub(&self.accumulator(begin).to_bytes());
+        } else {
+            for i in begin..end {
+                out.add(self.item(i).id.as_bytes());
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+fn encode<const N: usize>(
+    storage: &NegentropyStorageVector<N>,
+    prefix_accumulators: bool,
+    sink: &mut dyn FnMut(&[u8]) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let size: usize = storage.size()?;
+    let header = SnapshotHeader {
+        count: size as u64,
+        prefix_accumulators,
+    };
+
+    let mut encoder: Encoder = Encoder {
+        buf: Vec::with_capacity(CHUNK_SIZE),
+        crc: Crc32::new(),
+        sink,
+    };
+
+    encoder.write(&header.to_bytes::<N>()?)?;
+
+    storage.iterate(0, size, &mut |item: Item<N>, _| {
+        encoder.write(&item.timestamp.to_le_bytes())?;
+        encoder.write(item.id.as_bytes())?;
+        Ok(true)
+    })?;
+
+    if prefix_accumulators {
+        let mut acc: Accumulator<N> = Accumulator::new();
+        encoder.write(&acc.to_bytes())?;
+
+        storage.iterate(0, size, &mut |item: Item<N>, _| {
+            acc.add(item.id.as_bytes());
+            encoder.write(&acc.to_bytes())?;
+            Ok(true)
+        })?;
+    }
+
+    encoder.finish()
+}
+
+fn decode<S, const N: usize>(
+    source: &mut S,
+    len: Option<usize>,
+) -> Result<NegentropyStorageVector<N>, Error>
+where
+    S: Source,
+{
+    let mut crc: Crc32 = Crc32::new();
+
+    let mut header: [u8; SNAPSHOT_HEADER_SIZE] = [0u8; SNAPSHOT_HEADER_SIZE];
+    source.read(&mut header)?;
+    crc.update(&header);
+    let header: SnapshotHeader = SnapshotHeader::parse::<N>(&header)?;
+
+    let snapshot_size: usize = header
+        .snapshot_size::<N>()
+        .ok_or(SnapshotError::Truncated)?;
+    if let Some(len) = len {
+        if len < snapshot_size {
+            return Err(SnapshotError::Truncated.into());
+        }
+    }
+
+    // Never trust the count to pre-allocate when the length is unknown
+    let count: usize = header.count as usize;
+    let record_size: usize = SnapshotHeader::record_size::<N>();
+    let capacity: usize = match len {
+        Some(..) => count,
+        None => count.min(CHUNK_SIZE / record_size),
+    };
+    let mut items: Vec<Item<N>> = Vec::with_capacity(capacity);
+
+    let mut buf: Vec<u8> = vec![0u8; (CHUNK_SIZE / record_size).max(1) * record_size];
+    let mut remaining: usize = count;
+    while remaining > 0 {
+        let n: usize = remaining.min(buf.len() / record_size);
+        let chunk: &mut [u8] = &mut buf[..n * record_size];
+        source.read(chunk)?;
+        crc.update(chunk);
+
+        for record in chunk.chunks_exact(record_size) {
+            let mut timestamp: [u8; TIMESTAMP_SIZE] = [0u8; TIMESTAMP_SIZE];
+            timestamp.copy_from_slice(&record[..TIMESTAMP_SIZE]);
+            let mut id: [u8; N] = [0u8; N];
+            id.copy_from_slice(&record[TIMESTAMP_SIZE..]);
+
+            let item: Item<N> =
+                Item::with_timestamp_and_id(u64::from_le_bytes(timestamp), Id::from_byte_array(id));
+
+            if let Some(last) = items.last() {
+                if *last >= item {
+                    return Err(SnapshotError::UnsortedItems.into());
+                }
+            }
+
+            items.push(item);
+        }
+
+        remaining -= n;
+    }
+
+    if header.prefix_accumulators {
+        let mut acc: Accumulator<N> = Accumulator::new();
+        let mut stored: [u8; N] = [0u8; N];
+
+        for i in 0..=count {
+            source.read(&mut stored)?;
+            crc.update(&stored);
+
+            if stored != acc.to_bytes() {
+                return Err(SnapshotError::AccumulatorMismatch.into());
+            }
+
+            if let Some(item) = items.get(i) {
+                acc.add(item.id.as_bytes());
+            }
+        }
+    }
+
+    let mut checksum: [u8; CHECKSUM_SIZE] = [0u8; CHECKSUM_SIZE];
+    source.read(&mut checksum)?;
+    if u32::from_le_bytes(checksum) != crc.finish() {
+        return Err(SnapshotError::ChecksumMismatch.into());
+    }
+
+    Ok(NegentropyStorageVector::from_sorted_unchecked(items))
+}
+
+struct Encoder<'a> {
+    buf: Vec<u8>,
+    crc: Crc32,
+    sink: &'a mut dyn FnMut(&[u8]) -> Result<(), Error>,
+}
+
+impl Encoder<'_> {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.crc.update(bytes);
+        self.buf.extend_from_slice(bytes);
+
+        if self.buf.len() >= CHUNK_SIZE {
+            (self.sink)(&self.buf)?;
+            self.buf.clear();
+        }
+
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(), Error> {
+        let checksum: u32 = self.crc.finish();
+        self.buf.extend_from_slice(&checksum.to_le_bytes());
+        (self.sink)(&self.buf)
+    }
+}
+
+trait Source {
+    /// Fill `buf`
+    fn read(&mut self, buf: &mut [u8]) -> Result<(), Error>;
+}
+
+impl Source for &[u8] {
+    fn read(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        if self.len() < buf.len() {
+            return Err(SnapshotError::Truncated.into());
+        }
+
+        let (bytes, rest) = self.split_at(buf.len());
+        buf.copy_from_slice(bytes);
+        *self = rest;
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+struct ReadSource<R>(R);
+
+#[cfg(feature = "std")]
+impl<R> Source for ReadSource<R>
+where
+    R: std::io::Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        self.0.read_exact(buf).map_err(io_error)
+    }
+}
+
+#[cfg(feature = "std")]
+fn io_error(e: std::io::Error) -> Error {
+    match e.kind() {
+        std::io::ErrorKind::UnexpectedEof => SnapshotError::Truncated.into(),
+        kind => SnapshotError::Io(kind).into(),
+    }
+}
+
+/// CRC-32 (IEEE)
+struct Crc32(u32);
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table: [u32; 256] = [0; 256];
+    let mut i: usize = 0;
+    while i < 256 {
+        let mut c: u32 = i as u32;
+        let mut k: usize = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xEDB8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+impl Crc32 {
+    #[inline]
+    fn new() -> Self {
+        Self(0xFFFF_FFFF)
+    }
+
+    #[inline]
+    fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes.iter() {
+            self.0 = CRC32_TABLE[((self.0 ^ *byte as u32) & 0xFF) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    #[inline]
+    fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Negentropy, Sha256};
+
+    fn storage(n: u64) -> NegentropyStorageVector {
+        (0..n)
+            .map(|i| {
+                let mut id = [0u8; 32];
+                id[..8].copy_from_slice(&i.wrapping_mul(0x9E37_79B9_7F4A_7C15).to_be_bytes());
+                Item::with_timestamp_and_id(i / 3, Id::from_byte_array(id))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_crc32() {
+        let mut crc = Crc32::new();
+        crc.update(b"123456789");
+        assert_eq!(crc.finish(), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_snapshot_roundtrip() {
+        for n in [0, 1, 5000] {
+            let storage = storage(n);
+
+            for prefix_accumulators in [false, true] {
+                let bytes = storage.to_bytes(prefix_accumulators).unwrap();
+                let header = SnapshotHeader::parse::<32>(&bytes).unwrap();
+                assert_eq!(header.count, n);
+                assert_eq!(header.prefix_accumulators, prefix_accumulators);
+                assert_eq!(header.snapshot_size::<32>(), Some(bytes.len()));
+
+                assert_eq!(
+                    NegentropyStorageVector::from_bytes(&bytes).unwrap(),
+                    storage
+                );
+
+                #[cfg(feature = "std")]
+                {
+                    let mut file: Vec<u8> = Vec::new();
+                    storage.save_to(&mut file, prefix_accumulators).unwrap();
+                    assert_eq!(file, bytes);
+                    assert_eq!(
+                        NegentropyStorageVector::load_from(file.as_slice()).unwrap(),
+                        storage
+                    );
+                }
+            }
+        }
+
+        // Other ID sizes
+        let storage: NegentropyStorageVector<20> =
+            vec![Item::with_timestamp_and_id(1, Id::from_byte_array([1; 20]))]
+                .into_iter()
+                .collect();
+        let bytes = storage.to_bytes(true).unwrap();
+        assert_eq!(
+            NegentropyStorageVector::from_bytes(&bytes).unwrap(),
+            storage
+        );
+        assert_eq!(
+            NegentropyStorageVector::<32>::from_bytes(&bytes),
+            Err(Error::Snapshot(SnapshotError::IdSizeMismatch(20)))
+        );
+
+        assert_eq!(
+            NegentropyStorageVector::new().to_bytes(false),
+            Err(Error::NotSealed)
+        );
+    }
+
+    #[test]
+    fn test_storage_bytes() {
+        let vector = storage(1000);
+        let mut bytes = vector.to_bytes(false).unwrap();
+
+        for prefix_accumulators in [false, true] {
+            let bytes = vector.to_bytes(prefix_accumulators).unwrap();
+            let view = NegentropyStorageBytes::new(bytes.as_slice()).unwrap();
+            view.validate().unwrap();
+            assert_eq!(view.has_prefix_accumulators(), prefix_accumulators);
+            assert_eq!(view.size().unwrap(), 1000);
+            assert_eq!(view.get_item(1000).unwrap(), None);
+
+            for (begin, end) in [(0, 0), (0, 1000), (17, 513), (999, 1000)] {
+                assert_eq!(
+                    view.fingerprint(&Sha256, begin, end).unwrap().to_bytes(),
+                    vector.fingerprint(&Sha256, begin, end).unwrap().to_bytes()
+                );
+
+                let bound = Bound::from_item(&vector.get_item(begin).unwrap().unwrap_or_default());
+                assert_eq!(
+                    view.find_lower_bound(0, 1000, &bound),
+                    vector.find_lower_bound(0, 1000, &bound)
+                );
+            }
+
+            assert_eq!(
+                view.fingerprint(&Sha256, 10, 1001).unwrap_err(),
+                Error::BadRange
+            );
+        }
+
+        // Sessions over the bytes
+        let mut client = Negentropy::owned(storage(500), 0).unwrap();
+        let mut relay =
+            Negentropy::owned(NegentropyStorageBytes::new(bytes.clone()).unwrap(), 0).unwrap();
+        let mut have_ids = Vec::new();
+        let mut need_ids = Vec::new();
+        let mut msg = client.initiate().unwrap();
+        loop {
+            let response = relay.reconcile(&msg).unwrap();
+            match client
+                .reconcile_with_ids(&response, &mut have_ids, &mut need_ids)
+                .unwrap()
+            {
+                Some(next) => msg = next,
+                None => break,
+            }
+        }
+        assert!(have_ids.is_empty());
+        assert_eq!(need_ids.len(), 500);
+
+        // Corrupted
+        assert_eq!(
+            NegentropyStorageBytes::new(&bytes[..bytes.len() - 1]).unwrap_err(),
+            Error::Snapshot(SnapshotError::Truncated)
+        );
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        let view = NegentropyStorageBytes::new(bytes).unwrap();
+        assert_eq!(
+            view.validate(),
+            Err(Error::Snapshot(SnapshotError::ChecksumMismatch))
+        );
+    }
+
+    #[test]
+    fn test_snapshot_corrupted() {
+        let storage = storage(100);
+        let bytes = storage.to_bytes(true).unwrap();
+        let err = |bytes: &[u8]| NegentropyStorageVector::<32>::from_bytes(bytes).unwrap_err();
+
+        let mut corrupted = bytes.clone();
+        corrupted[0] = b'X';
+        assert_eq!(err(&corrupted), Error::Snapshot(SnapshotError::BadMagic));
+
+        let mut corrupted = bytes.clone();
+        corrupted[4] = 2;
+        assert_eq!(
+            err(&corrupted),
+            Error::Snapshot(SnapshotError::UnsupportedVersion(2))
+        );
+
+        let mut corrupted = bytes.clone();
+        corrupted[5] = 0b11;
+        assert_eq!(
+            err(&corrupted),
+            Error::Snapshot(SnapshotError::UnknownFlags(0b11))
+        );
+
+        assert_eq!(
+            err(&bytes[..bytes.len() - 1]),
+            Error::Snapshot(SnapshotError::Truncated)
+        );
+
+        let mut corrupted = bytes.clone();
+        corrupted.push(0);
+        assert_eq!(
+            err(&corrupted),
+            Error::Snapshot(SnapshotError::TrailingBytes)
+        );
+
+        // Flipped ID bit
+        let mut corrupted = bytes.clone();
+        corrupted[SNAPSHOT_HEADER_SIZE + 8 + 31] ^= 1;
+        assert_eq!(
+            err(&corrupted),
+            Error::Snapshot(SnapshotError::AccumulatorMismatch)
+        );
+
+        // Flipped checksum bit
+        let mut corrupted = bytes.clone();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 1;
+        assert_eq!(
+            err(&corrupted),
+            Error::Snapshot(SnapshotError::ChecksumMismatch)
+        );
+
+        // Swapped records
+        let record_size = SnapshotHeader::record_size::<32>();
+        let mut corrupted = storage.to_bytes(false).unwrap();
+        let (first, second) = corrupted[SNAPSHOT_HEADER_SIZE..].split_at_mut(record_size);
+        first.swap_with_slice(&mut second[..record_size]);
+        assert_eq!(
+            err(&corrupted),
+            Error::Snapshot(SnapshotError::UnsortedItems)
+        );
+
+        // Huge count
+        let mut corrupted = bytes;
+        corrupted[8..16].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(err(&corrupted), Error::Snapshot(SnapshotError::Truncated));
+        #[cfg(feature = "std")]
+        assert_eq!(
+            NegentropyStorageVector::<32>::load_from(corrupted.as_slice()),
+            Err(Error::Snapshot(SnapshotError::Truncated))
+        );
+    }
+}
@@ -5,8 +5,15 @@
 /// Implemented protocol version
 pub const PROTOCOL_VERSION: u64 = 0x61; // Version 1
 
-/// ID Size
+/// Experimental ID prefix extension protocol version (non-standard)
+#[cfg(feature = "experimental-id-prefix")]
+pub const ID_PREFIX_PROTOCOL_VERSION: u64 = 0x6F;
+
+/// ID Size (protocol default)
 pub const ID_SIZE: usize = 32;
 
+/// Max ID size supported by the [`Accumulator`](crate::Accumulator)
+pub const MAX_ID_SIZE: usize = 64;
+
 /// Fingerprint Size
 pub const FINGERPRINT_SIZE: usize = 16;
@@ -13,12 +13,12 @@ pub fn get_byte_array<const N: usize>(encoded: &mut &[u8]) -> Result<[u8; N], Er
     Ok(get_bytes(encoded, N)?.try_into()?)
 }
 
-pub fn get_bytes<'a>(encoded: &'a mut &[u8], n: usize) -> Result<&'a [u8], Error> {
+pub fn get_bytes<'a>(encoded: &mut &'a [u8], n: usize) -> Result<&'a [u8], Error> {
     if encoded.len() < n {
         return Err(Error::ParseEndsPrematurely);
     }
-    let res: &[u8] = &encoded[..n];
-    *encoded = encoded.get(n..).unwrap_or_default();
+    let (res, rest) = encoded.split_at(n);
+    *encoded = rest;
     Ok(res)
 }
 
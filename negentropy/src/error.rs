@@ -2,9 +2,12 @@
 // Copyright (c) 2023 Yuki Kishimoto
 // Distributed under the MIT software license
 
+use alloc::string::String;
 use core::array::TryFromSliceError;
 use core::fmt;
 
+use crate::binary::SnapshotError;
+
 /// Error
 #[derive(Debug, PartialEq, Eq)]
 pub enum Error {
@@ -12,6 +15,8 @@ pub enum Error {
     IdTooBig,
     /// Invalid ID size
     InvalidIdSize,
+    /// Invalid hex
+    InvalidHex,
     /// Frame size limit too small
     FrameSizeLimitTooSmall,
     /// Not sealed
@@ -34,10 +39,20 @@ pub enum Error {
     InvalidProtocolVersion,
     /// Unsupported protocol version
     UnsupportedProtocolVersion,
+    /// No supported protocol versions
+    NoSupportedVersions,
     /// Try from slice error
     TryFromSlice,
     /// Bad range
     BadRange,
+    /// Invalid ID prefix length
+    InvalidIdPrefixLength,
+    /// Items not sorted or not unique
+    UnsortedItems,
+    /// Invalid binary snapshot
+    Snapshot(SnapshotError),
+    /// Storage error (i.e. a database failure in a custom [`NegentropyStorageBase`](crate::NegentropyStorageBase))
+    Storage(String),
 }
 
 #[cfg(feature = "std")]
@@ -48,6 +63,7 @@ impl fmt::Display for Error {
         match self {
             Self::IdTooBig => write!(f, "ID too big"),
             Self::InvalidIdSize => write!(f, "Invalid ID size"),
+            Self::InvalidHex => write!(f, "Invalid hex"),
             Self::FrameSizeLimitTooSmall => write!(f, "Frame size limit too small"),
             Self::NotSealed => write!(f, "Not sealed"),
             Self::AlreadySealed => write!(f, "Already sealed"),
@@ -61,8 +77,13 @@ impl fmt::Display for Error {
             Self::UnsupportedProtocolVersion => {
                 write!(f, "server does not support our negentropy protocol version")
             }
+            Self::NoSupportedVersions => write!(f, "no supported protocol versions"),
             Self::TryFromSlice => write!(f, "could not convert slice to array"),
             Self::BadRange => write!(f, "bad range"),
+            Self::InvalidIdPrefixLength => write!(f, "invalid ID prefix length"),
+            Self::UnsortedItems => write!(f, "items not sorted or not unique"),
+            Self::Snapshot(e) => write!(f, "invalid snapshot: {}", e),
+            Self::Storage(e) => write!(f, "storage error: {}", e),
         }
     }
 }
@@ -72,3 +93,9 @@ impl From<TryFromSliceError> for Error {
         Self::TryFromSlice
     }
 }
+
+impl From<SnapshotError> for Error {
+    fn from(e: SnapshotError) -> Self {
+        Self::Snapshot(e)
+    }
+}
@@ -0,0 +1,103 @@
+// Copyright (c) 2023 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! Fingerprint hash functions
+//!
+//! The negentropy protocol specifies SHA-256 for fingerprints: use [`Sha256`] (the default)
+//! to stay compatible with other implementations. The alternative hashers are **non-standard**:
+//! both peers must be configured with the same hasher (and key, if any), otherwise no
+//! fingerprint will ever match and reconciliation degrades to exchanging full ID lists.
+
+#[cfg(feature = "blake3")]
+use core::fmt;
+
+use crate::sha256;
+
+/// Fingerprint hasher
+pub trait FingerprintHasher {
+    /// Hash the fingerprint input (the accumulator followed by the varint-encoded set size)
+    ///
+    /// Only the first [`FINGERPRINT_SIZE`](crate::FINGERPRINT_SIZE) bytes are used.
+    fn hash(&self, input: &[u8]) -> [u8; 32];
+}
+
+/// SHA-256 hasher (protocol default)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sha256;
+
+impl FingerprintHasher for Sha256 {
+    #[inline]
+    fn hash(&self, input: &[u8]) -> [u8; 32] {
+        sha256::hash(input)
+    }
+}
+
+/// BLAKE3 hasher (non-standard)
+#[cfg(feature = "blake3")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Blake3;
+
+#[cfg(feature = "blake3")]
+impl FingerprintHasher for Blake3 {
+    #[inline]
+    fn hash(&self, input: &[u8]) -> [u8; 32] {
+        blake3::hash(input).into()
+    }
+}
+
+/// Keyed BLAKE3 hasher (non-standard)
+///
+/// Fingerprints can't be computed (or probed) without knowing the key.
+#[cfg(feature = "blake3")]
+#[derive(Clone, PartialEq, Eq)]
+pub struct Blake3Keyed {
+    key: [u8; 32],
+}
+
+#[cfg(feature = "blake3")]
+impl fmt::Debug for Blake3Keyed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Blake3Keyed").finish()
+    }
+}
+
+#[cfg(feature = "blake3")]
+impl Blake3Keyed {
+    /// New keyed hasher
+    #[inline]
+    pub fn new(key: [u8; 32]) -> Self {
+        Self { key }
+    }
+}
+
+#[cfg(feature = "blake3")]
+impl FingerprintHasher for Blake3Keyed {
+    #[inline]
+    fn hash(&self, input: &[u8]) -> [u8; 32] {
+        blake3::keyed_hash(&self.key, input).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha256_hasher() {
+        assert_eq!(Sha256.hash(b"abc"), sha256::hash(b"abc"));
+    }
+
+    #[cfg(feature = "blake3")]
+    #[test]
+    fn test_blake3_hasher() {
+        let plain = Blake3.hash(b"abc");
+        assert_ne!(plain, Sha256.hash(b"abc"));
+
+        let keyed = Blake3Keyed::new([0x01; 32]);
+        assert_ne!(keyed.hash(b"abc"), plain);
+        assert_ne!(
+            keyed.hash(b"abc"),
+            Blake3Keyed::new([0x02; 32]).hash(b"abc")
+        );
+    }
+}
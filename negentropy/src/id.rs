@@ -2,42 +2,56 @@
 // Copyright (c) 2023 Yuki Kishimoto
 // Distributed under the MIT software license
 
+use alloc::string::String;
+use core::fmt;
 use core::ops::{Deref, DerefMut};
+use core::str::FromStr;
 
 use crate::error::Error;
 use crate::ID_SIZE;
 
+const HEX_CHARS: &[u8; 16] = b"0123456789abcdef";
+
 /// Bytes
+///
+/// `N` is the ID size: the negentropy protocol uses 32-byte IDs (the default).
+///
+/// Formatted (`{}` or `{:x}`) and parsed ([`FromStr`]) as lowercase hex.
 #[repr(transparent)]
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct Id([u8; ID_SIZE]);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Id<const N: usize = ID_SIZE>([u8; N]);
+
+impl<const N: usize> Default for Id<N> {
+    #[inline]
+    fn default() -> Self {
+        Self([0u8; N])
+    }
+}
 
-impl Deref for Id {
-    type Target = [u8; Self::LEN];
+impl<const N: usize> Deref for Id<N> {
+    type Target = [u8; N];
 
     fn deref(&self) -> &Self::Target {
         &self.0
     }
 }
 
-impl DerefMut for Id {
+impl<const N: usize> DerefMut for Id<N> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.0
     }
 }
 
-impl Id {
-    const LEN: usize = ID_SIZE;
-
+impl<const N: usize> Id<N> {
     /// Construct from byte array
     #[deprecated(since = "1.0.0", note = "Use `from_byte_array` instead")]
-    pub fn new(bytes: [u8; ID_SIZE]) -> Self {
+    pub fn new(bytes: [u8; N]) -> Self {
         Self(bytes)
     }
 
-    /// Construct event ID from 32-byte array
+    /// Construct event ID from `N`-byte array
     #[inline]
-    pub const fn from_byte_array(bytes: [u8; Self::LEN]) -> Self {
+    pub const fn from_byte_array(bytes: [u8; N]) -> Self {
         Self(bytes)
     }
 
@@ -45,27 +59,257 @@ impl Id {
     #[inline]
     pub fn from_slice(slice: &[u8]) -> Result<Self, Error> {
         // Check len
-        if slice.len() != Self::LEN {
+        if slice.len() != N {
             return Err(Error::InvalidIdSize);
         }
 
         // Copy bytes
-        let mut bytes: [u8; Self::LEN] = [0u8; Self::LEN];
+        let mut bytes: [u8; N] = [0u8; N];
         bytes.copy_from_slice(slice);
 
         // Construct
         Ok(Self::from_byte_array(bytes))
     }
 
+    /// Construct from `2 * N` hex chars (either case)
+    ///
+    /// Returns [`Error::InvalidIdSize`] if the length isn't `2 * N`
+    /// and [`Error::InvalidHex`] if a char isn't a hex digit.
+    pub fn from_hex<T>(hex: T) -> Result<Self, Error>
+    where
+        T: AsRef<[u8]>,
+    {
+        let hex: &[u8] = hex.as_ref();
+
+        // Check len
+        if hex.len() != N * 2 {
+            return Err(Error::InvalidIdSize);
+        }
+
+        // Decode
+        let mut bytes: [u8; N] = [0u8; N];
+        for (byte, chunk) in bytes.iter_mut().zip(hex.chunks_exact(2)) {
+            *byte = (decode_hex_digit(chunk[0])? << 4) | decode_hex_digit(chunk[1])?;
+        }
+
+        Ok(Self::from_byte_array(bytes))
+    }
+
+    /// Encode as lowercase hex
+    pub fn to_hex(&self) -> String {
+        let mut hex: String = String::with_capacity(N * 2);
+        for byte in self.0.iter() {
+            hex.push(HEX_CHARS[(byte >> 4) as usize] as char);
+            hex.push(HEX_CHARS[(byte & 0x0f) as usize] as char);
+        }
+        hex
+    }
+
     /// Return the inner value
     #[inline]
-    pub fn to_bytes(self) -> [u8; Self::LEN] {
+    pub fn to_bytes(self) -> [u8; N] {
         self.0
     }
 
     /// Return reference to the inner value
     #[inline]
-    pub fn as_bytes(&self) -> &[u8; Self::LEN] {
+    pub fn as_bytes(&self) -> &[u8; N] {
         &self.0
     }
 }
+
+#[inline]
+fn decode_hex_digit(c: u8) -> Result<u8, Error> {
+    match c {
+        b'0'..=b'9' => Ok(c - b'0'),
+        b'a'..=b'f' => Ok(c - b'a' + 10),
+        b'A'..=b'F' => Ok(c - b'A' + 10),
+        _ => Err(Error::InvalidHex),
+    }
+}
+
+fn write_hex(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    for byte in bytes.iter() {
+        let chars: [u8; 2] = [
+            HEX_CHARS[(byte >> 4) as usize],
+            HEX_CHARS[(byte & 0x0f) as usize],
+        ];
+        // Always ASCII
+        f.write_str(core::str::from_utf8(&chars).map_err(|_| fmt::Error)?)?;
+    }
+
+    Ok(())
+}
+
+impl<const N: usize> fmt::LowerHex for Id<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            f.write_str("0x")?;
+        }
+
+        write_hex(f, &self.0)
+    }
+}
+
+impl<const N: usize> fmt::Display for Id<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_hex(f, &self.0)
+    }
+}
+
+impl<const N: usize> FromStr for Id<N> {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_hex(s)
+    }
+}
+
+/// Hex string in human-readable formats (e.g. JSON), bytes otherwise
+#[cfg(feature = "serde")]
+impl<const N: usize> serde::Serialize for Id<N> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&self.to_hex())
+        } else {
+            serializer.serialize_bytes(&self.0)
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, const N: usize> serde::Deserialize<'de> for Id<N> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct IdVisitor<const N: usize>;
+
+        impl<'de, const N: usize> serde::de::Visitor<'de> for IdVisitor<N> {
+            type Value = Id<N>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{} bytes or {} hex chars", N, N * 2)
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                Id::from_hex(v).map_err(E::custom)
+            }
+
+            fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                Id::from_slice(v).map_err(E::custom)
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: serde::de::SeqAccess<'de>,
+            {
+                let mut bytes: [u8; N] = [0u8; N];
+                for (i, byte) in bytes.iter_mut().enumerate() {
+                    *byte = seq
+                        .next_element()?
+                        .ok_or_else(|| serde::de::Error::invalid_length(i, &self))?;
+                }
+
+                if seq.next_element::<u8>()?.is_some() {
+                    return Err(serde::de::Error::custom(Error::InvalidIdSize));
+                }
+
+                Ok(Id::from_byte_array(bytes))
+            }
+        }
+
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(IdVisitor)
+        } else {
+            deserializer.deserialize_bytes(IdVisitor)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::format;
+    use alloc::string::ToString;
+
+    use super::*;
+
+    const HEX: &str = "00112233445566778899aabbccddeeff0123456789abcdefa0b1c2d3e4f5a6b7";
+
+    #[test]
+    fn test_hex() {
+        let id = Id::from_hex(HEX).unwrap();
+        assert_eq!(id.as_bytes()[..4], [0x00, 0x11, 0x22, 0x33]);
+        assert_eq!(id.to_hex(), HEX);
+        assert_eq!(id.to_string(), HEX);
+        assert_eq!(format!("{:x}", id), HEX);
+        assert_eq!(format!("{:#x}", id), format!("0x{}", HEX));
+        assert_eq!(format!("{:#}", id), HEX);
+        assert_eq!(HEX.parse::<Id>().unwrap(), id);
+        assert_eq!(Id::from_hex(HEX.to_uppercase()).unwrap(), id);
+
+        let id = Id::<4>::from_hex("deadBEEF").unwrap();
+        assert_eq!(id.to_bytes(), [0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(id.to_string(), "deadbeef");
+    }
+
+    #[test]
+    fn test_invalid_hex() {
+        assert_eq!(
+            Id::<ID_SIZE>::from_hex(&HEX[..62]),
+            Err(Error::InvalidIdSize)
+        );
+        assert_eq!(
+            Id::<ID_SIZE>::from_hex(&HEX[..63]),
+            Err(Error::InvalidIdSize)
+        );
+        assert_eq!(
+            Id::<ID_SIZE>::from_hex(HEX.replace('a', "g")),
+            Err(Error::InvalidHex)
+        );
+        assert_eq!(Id::<2>::from_hex("+1ff"), Err(Error::InvalidHex));
+        assert_eq!(Id::<2>::from_hex("0ａ"), Err(Error::InvalidHex));
+        assert_eq!("".parse::<Id>(), Err(Error::InvalidIdSize));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde() {
+        use serde_test::{assert_de_tokens_error, assert_tokens, Configure, Token};
+
+        let id = Id::<4>::from_hex("deadbeef").unwrap();
+        assert_tokens(&id.readable(), &[Token::Str("deadbeef")]);
+        assert_tokens(&id.compact(), &[Token::Bytes(&[0xde, 0xad, 0xbe, 0xef])]);
+
+        assert_eq!(
+            serde_json::to_string(&Id::<ID_SIZE>::from_hex(HEX).unwrap()).unwrap(),
+            format!("\"{}\"", HEX)
+        );
+        assert_eq!(
+            serde_json::from_str::<Id<ID_SIZE>>(&format!("\"{}\"", HEX)).unwrap(),
+            Id::from_hex(HEX).unwrap()
+        );
+
+        assert_de_tokens_error::<serde_test::Readable<Id<4>>>(
+            &[Token::Str("deadbeefff")],
+            "Invalid ID size",
+        );
+        assert_de_tokens_error::<serde_test::Readable<Id<4>>>(
+            &[Token::Str("deadbeeg")],
+            "Invalid hex",
+        );
+        assert_de_tokens_error::<serde_test::Compact<Id<4>>>(
+            &[Token::Bytes(&[0xde, 0xad])],
+            "Invalid ID size",
+        );
+    }
+}
@@ -0,0 +1,310 @@
+// Copyright (c) 2023 Doug Hoyte
+// Copyright (c) 2023 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! Experimental ID prefix extension (**non-standard**)
+//!
+//! Negotiated with the [`ID_PREFIX_PROTOCOL_VERSION`] version byte. When both peers opted in
+//! (see [`Negentropy::enable_id_prefixes`]), the initiator sends the first `prefix_len` bytes of
+//! each ID instead of the full ID list:
+//!
+//! ```text
+//! IdPrefixList (mode 3): <bound> 3 <varint num prefixes> <varint prefix len> <prefix>*
+//! IdPrefixDiff (mode 4): <bound> 4 <varint num ids> <id>* <varint num prefixes> <prefix>* <fingerprint>
+//! ```
+//!
+//! The non-initiator replies with the full IDs the initiator lacks, the prefixes of the initiator
+//! items it lacks and the fingerprint of the items matched by prefix, which the initiator verifies.
+//! When prefixes collide, either within a peer (detected before replying) or across peers
+//! (detected by the fingerprint), the range falls back to a full [`Mode::IdList`].
+
+use alloc::vec::Vec;
+use core::cmp;
+
+use crate::constants::{FINGERPRINT_SIZE, ID_PREFIX_PROTOCOL_VERSION};
+use crate::encoding::{decode_var_int, encode_var_int, get_byte_array, get_bytes};
+use crate::{
+    Accumulator, Bound, Error, FingerprintHasher, Id, Item, Mode, Negentropy, NegentropyStorageBase,
+};
+
+#[cfg(feature = "std")]
+type Set<T> = std::collections::HashSet<T>;
+#[cfg(not(feature = "std"))]
+type Set<T> = alloc::collections::BTreeSet<T>;
+
+impl<'a, T, H, const N: usize> Negentropy<'a, T, H, N>
+where
+    T: NegentropyStorageBase<N>,
+    H: FingerprintHasher,
+{
+    /// Enable the experimental ID prefix extension ([`ID_PREFIX_PROTOCOL_VERSION`])
+    ///
+    /// Adds the extension to the supported [protocol versions](Negentropy::supported_versions).
+    /// ID lists sent by the initiator carry `prefix_len` bytes per ID instead of `N`.
+    /// The non-initiator must opt in as well (`prefix_len` is ignored on its side),
+    /// otherwise the initiator transparently falls back to the standard protocol.
+    ///
+    /// Must be called before [`Negentropy::initiate`].
+    pub fn enable_id_prefixes(&mut self, prefix_len: usize) -> Result<(), Error> {
+        if self.is_initiator {
+            return Err(Error::AlreadyBuiltInitialMessage);
+        }
+
+        if prefix_len == 0 || prefix_len > N {
+            return Err(Error::InvalidIdPrefixLength);
+        }
+
+        self.supported_versions.insert(ID_PREFIX_PROTOCOL_VERSION)?;
+        self.id_prefix_len = Some(prefix_len);
+
+        Ok(())
+    }
+
+    /// Build an [`Mode::IdPrefixList`] for the range, or `None` if the extension is disabled
+    /// or our own items collide on their prefix.
+    pub(crate) fn encode_id_prefix_list(
+        &mut self,
+        lower: usize,
+        upper: usize,
+        upper_bound: Bound<N>,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let prefix_len: usize = match self.id_prefix_len {
+            Some(prefix_len) if self.protocol_version == ID_PREFIX_PROTOCOL_VERSION => prefix_len,
+            _ => return Ok(None),
+        };
+
+        let mut prefixes: Vec<u8> = Vec::with_capacity((upper - lower) * prefix_len);
+        let mut seen: Set<Vec<u8>> = Set::new();
+        let mut collision: bool = false;
+
+        self.storage
+            .iterate(lower, upper, &mut |item: Item<N>, _| {
+                let prefix: &[u8] = &item.id[..prefix_len];
+                if !seen.insert(prefix.to_vec()) {
+                    collision = true;
+                    return Ok(false);
+                }
+
+                prefixes.extend(prefix);
+                Ok(true)
+            })?;
+
+        if collision {
+            return Ok(None);
+        }
+
+        let mut o: Vec<u8> = Vec::with_capacity(10 + 10 + 10 + prefixes.len());
+        o.extend(self.encode_bound(&upper_bound));
+        o.extend(self.encode_mode(Mode::IdPrefixList));
+        o.extend(encode_var_int((upper - lower) as u64));
+        o.extend(encode_var_int(prefix_len as u64));
+        o.extend(prefixes);
+
+        Ok(Some(o))
+    }
+
+    /// Answer an [`Mode::IdPrefixList`] with an [`Mode::IdPrefixDiff`]
+    ///
+    /// Returns `None` if the diff can't be built (prefix collision or frame size limit):
+    /// the caller must reply with the full ID list instead.
+    pub(crate) fn encode_id_prefix_diff(
+        &mut self,
+        query: &mut &[u8],
+        lower: usize,
+        upper: usize,
+        curr_bound: Bound<N>,
+        output_len: usize,
+        have_ids: &mut Vec<Id<N>>,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let num_prefixes: u64 = decode_var_int(query)?;
+        let prefix_len: usize = decode_var_int(query)? as usize;
+
+        if prefix_len == 0 || prefix_len > N {
+            return Err(Error::InvalidIdPrefixLength);
+        }
+
+        let mut their_prefixes: Set<&[u8]> = Set::new();
+        let mut collision: bool = false;
+
+        for _ in 0..num_prefixes {
+            let prefix: &[u8] = get_bytes(query, prefix_len)?;
+            if !their_prefixes.insert(prefix) {
+                collision = true;
+            }
+        }
+
+        let mut matched: Set<Vec<u8>> = Set::new();
+        let mut acc: Accumulator<N> = Accumulator::new();
+        let mut missing_ids: Vec<Id<N>> = Vec::new();
+
+        self.storage
+            .iterate(lower, upper, &mut |item: Item<N>, _| {
+                let prefix: &[u8] = &item.id[..prefix_len];
+                if their_prefixes.contains(prefix) {
+                    if !matched.insert(prefix.to_vec()) {
+                        collision = true;
+                        return Ok(false);
+                    }
+
+                    acc.add(item.id.as_bytes());
+                } else {
+                    missing_ids.push(item.id);
+                }
+
+                Ok(true)
+            })?;
+
+        if collision {
+            return Ok(None);
+        }
+
+        let missing_prefixes: Vec<&[u8]> = their_prefixes
+            .into_iter()
+            .filter(|prefix| !matched.contains(*prefix))
+            .collect();
+
+        let mut body: Vec<u8> = Vec::with_capacity(
+            10 + missing_ids.len() * N
+                + 10
+                + missing_prefixes.len() * prefix_len
+                + FINGERPRINT_SIZE,
+        );
+        body.extend(encode_var_int(missing_ids.len() as u64));
+        for id in missing_ids.iter() {
+            body.extend(id.iter());
+        }
+        body.extend(encode_var_int(missing_prefixes.len() as u64));
+        for prefix in missing_prefixes.into_iter() {
+            body.extend(prefix);
+        }
+        body.extend(
+            acc.get_fingerprint(&self.hasher, matched.len() as u64)?
+                .iter(),
+        );
+
+        if self.exceeded_frame_size_limit(output_len + body.len()) {
+            return Ok(None);
+        }
+
+        have_ids.extend(missing_ids);
+
+        let mut o: Vec<u8> = Vec::with_capacity(10 + 10 + body.len());
+        o.extend(self.encode_bound(&curr_bound));
+        o.extend(self.encode_mode(Mode::IdPrefixDiff));
+        o.extend(body);
+
+        Ok(Some(o))
+    }
+
+    /// Skip the payload of an [`Mode::IdPrefixList`] or [`Mode::IdPrefixDiff`] range
+    pub(crate) fn skip_id_prefix_payload(
+        &self,
+        query: &mut &[u8],
+        mode: Mode,
+    ) -> Result<(), Error> {
+        match mode {
+            Mode::IdPrefixList => {
+                if self.is_initiator {
+                    return Err(Error::UnexpectedMode(mode.as_u64()));
+                }
+
+                let num_prefixes: u64 = decode_var_int(query)?;
+                let prefix_len: usize = decode_var_int(query)? as usize;
+
+                if prefix_len == 0 || prefix_len > N {
+                    return Err(Error::InvalidIdPrefixLength);
+                }
+
+                let len: usize = (num_prefixes as usize)
+                    .checked_mul(prefix_len)
+                    .ok_or(Error::ParseEndsPrematurely)?;
+                get_bytes(query, len)?;
+            }
+            Mode::IdPrefixDiff => {
+                let prefix_len: usize = match self.id_prefix_len {
+                    Some(prefix_len) if self.is_initiator => prefix_len,
+                    _ => return Err(Error::UnexpectedMode(mode.as_u64())),
+                };
+
+                let num_ids: u64 = decode_var_int(query)?;
+                let len: usize = (num_ids as usize)
+                    .checked_mul(N)
+                    .ok_or(Error::ParseEndsPrematurely)?;
+                get_bytes(query, len)?;
+
+                let num_prefixes: u64 = decode_var_int(query)?;
+                let len: usize = (num_prefixes as usize)
+                    .checked_mul(prefix_len)
+                    .ok_or(Error::ParseEndsPrematurely)?;
+                get_bytes(query, len)?;
+
+                get_bytes(query, FINGERPRINT_SIZE)?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Process an [`Mode::IdPrefixDiff`]
+    ///
+    /// Returns `false`, without reporting any ID, if the fingerprint of the items matched by prefix
+    /// doesn't match: the range must be reconciled again with the full ID list.
+    pub(crate) fn process_id_prefix_diff(
+        &mut self,
+        query: &mut &[u8],
+        lower: usize,
+        upper: usize,
+        have_ids: &mut Vec<Id<N>>,
+        need_ids: &mut Vec<Id<N>>,
+    ) -> Result<bool, Error> {
+        let prefix_len: usize = self
+            .id_prefix_len
+            .ok_or_else(|| Error::UnexpectedMode(Mode::IdPrefixDiff.as_u64()))?;
+
+        // Never trust the count to pre-allocate: reserve at most what the message can hold
+        let num_ids: u64 = decode_var_int(query)?;
+        let capacity: usize = cmp::min(num_ids, (query.len() / N.max(1)) as u64) as usize;
+        let mut their_ids: Vec<Id<N>> = Vec::with_capacity(capacity);
+        for _ in 0..num_ids {
+            let id: [u8; N] = get_byte_array(query)?;
+            their_ids.push(Id::from_byte_array(id));
+        }
+
+        let num_prefixes: u64 = decode_var_int(query)?;
+        let mut missing_prefixes: Set<&[u8]> = Set::new();
+        for _ in 0..num_prefixes {
+            missing_prefixes.insert(get_bytes(query, prefix_len)?);
+        }
+
+        let their_fingerprint: [u8; FINGERPRINT_SIZE] = get_byte_array(query)?;
+
+        let mut acc: Accumulator<N> = Accumulator::new();
+        let mut num_matched: u64 = 0;
+        let mut our_ids: Vec<Id<N>> = Vec::new();
+
+        self.storage
+            .iterate(lower, upper, &mut |item: Item<N>, _| {
+                if missing_prefixes.contains(&item.id[..prefix_len]) {
+                    our_ids.push(item.id);
+                } else {
+                    acc.add(item.id.as_bytes());
+                    num_matched += 1;
+                }
+
+                Ok(true)
+            })?;
+
+        let our_fingerprint: [u8; FINGERPRINT_SIZE] =
+            acc.get_fingerprint(&self.hasher, num_matched)?.to_bytes();
+
+        if our_fingerprint != their_fingerprint {
+            return Ok(false);
+        }
+
+        have_ids.extend(our_ids);
+        need_ids.extend(their_ids);
+
+        Ok(true)
+    }
+}
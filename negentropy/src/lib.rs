@@ -3,6 +3,30 @@
 // Distributed under the MIT software license
 
 //! Rust implementation of the negentropy set-reconciliation protocol.
+//!
+//! # ID size
+//!
+//! The protocol uses 32-byte IDs ([`ID_SIZE`]), which is the default for [`Id`], [`Item`], [`Bound`],
+//! [`Accumulator`], [`NegentropyStorageVector`] and [`Negentropy`].
+//! All of them are generic over the ID size, so other identifiers can be reconciled as well
+//! (e.g. 20-byte git object IDs, 16-byte UUIDs or 64-byte hashes):
+//!
+//! ```rust
+//! use negentropy::{Id, Negentropy, NegentropyStorageVector};
+//!
+//! let mut storage = NegentropyStorageVector::<20>::default();
+//! storage.insert(0, Id::from_byte_array([0xaa; 20])).unwrap();
+//! storage.seal().unwrap();
+//!
+//! let mut negentropy = Negentropy::borrowed(&storage, 0).unwrap();
+//! let _msg = negentropy.initiate().unwrap();
+//! ```
+//!
+//! Any size other than 32 bytes is a **non-standard profile** of the wire format: the message
+//! layout is unchanged, but every ID in an ID list is `N` bytes long, bounds carry ID prefixes
+//! of at most `N` bytes and fingerprints hash the `N`-byte accumulator (IDs summed mod `2^(8 * N)`)
+//! instead of the 32-byte one. Both peers must use the same ID size.
+//! Sizes up to [`MAX_ID_SIZE`] bytes are supported.
 
 #![forbid(unsafe_code)]
 #![warn(missing_docs)]
@@ -19,60 +43,87 @@ extern crate std;
 
 #[cfg(not(feature = "std"))]
 use alloc::collections::BTreeSet;
+use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::convert::TryFrom;
 #[cfg(feature = "std")]
 use std::collections::HashSet;
 
+pub mod binary;
 mod constants;
 mod encoding;
 mod error;
+pub mod hasher;
 mod id;
+#[cfg(feature = "experimental-id-prefix")]
+mod id_prefix;
+mod merged;
+mod session;
 mod sha256;
+#[cfg(feature = "std")]
+mod snapshot;
 mod storage;
 mod types;
+mod version;
 
-pub use self::constants::{FINGERPRINT_SIZE, ID_SIZE, PROTOCOL_VERSION};
+pub use self::binary::{NegentropyStorageBytes, SnapshotError};
+#[cfg(feature = "experimental-id-prefix")]
+pub use self::constants::ID_PREFIX_PROTOCOL_VERSION;
+pub use self::constants::{FINGERPRINT_SIZE, ID_SIZE, MAX_ID_SIZE, PROTOCOL_VERSION};
 use self::encoding::{decode_var_int, encode_var_int, get_byte_array, get_bytes};
 pub use self::error::Error;
+pub use self::hasher::{FingerprintHasher, Sha256};
 pub use self::id::Id;
+pub use self::merged::MergedStorage;
+pub use self::session::{NegentropyClient, NegentropyServer};
+#[cfg(feature = "std")]
+pub use self::snapshot::VersionedStorage;
 pub use self::storage::{NegentropyStorageBase, NegentropyStorageVector, Storage};
 use self::types::Mode;
-pub use self::types::{Bound, Item};
+pub use self::types::{Accumulator, Bound, Fingerprint, Item};
+pub use self::version::SupportedVersions;
 
 const MAX_U64: u64 = u64::MAX;
 const BUCKETS: usize = 16;
 const DOUBLE_BUCKETS: usize = BUCKETS * 2;
+/// Encoded size of the fingerprint range up to the `MAX_U64` bound (bound, mode and fingerprint)
+const REMAINING_RANGE_SIZE: usize = 1 + 1 + 1 + FINGERPRINT_SIZE;
 
 /// Negentropy
+///
+/// Plays both roles, checked at runtime: prefer the typed [`NegentropyClient`] and [`NegentropyServer`].
+///
+/// Fingerprints are computed with the `H` [`FingerprintHasher`] (SHA-256 by default)
+/// and IDs are `N` bytes long (32 by default): see the [crate-level docs](crate#id-size).
 #[derive(Debug)]
-pub struct Negentropy<'a, T> {
+pub struct Negentropy<'a, T, H = Sha256, const N: usize = ID_SIZE> {
     storage: Storage<'a, T>,
+    hasher: H,
     frame_size_limit: u64,
     is_initiator: bool,
+    supported_versions: SupportedVersions,
+    protocol_version: u64,
+    #[cfg(feature = "experimental-id-prefix")]
+    id_prefix_len: Option<usize>,
     last_timestamp_in: u64,
     last_timestamp_out: u64,
+    /// Needed IDs already reported for truncated ranges (see [`Negentropy::reconcile_with_diff`])
+    #[cfg(feature = "std")]
+    reported_need_ids: HashSet<Id<N>>,
+    #[cfg(not(feature = "std"))]
+    reported_need_ids: BTreeSet<Id<N>>,
 }
 
-impl<'a, T> Negentropy<'a, T>
+impl<'a, T, const N: usize> Negentropy<'a, T, Sha256, N>
 where
-    T: NegentropyStorageBase,
+    T: NegentropyStorageBase<N>,
 {
     /// Create new [`Negentropy`] instance
     ///
     /// Frame size limit must be `equal to 0` or `greater than 4096`
     pub fn new(storage: Storage<'a, T>, frame_size_limit: u64) -> Result<Self, Error> {
-        if frame_size_limit != 0 && frame_size_limit < 4096 {
-            return Err(Error::FrameSizeLimitTooSmall);
-        }
-
-        Ok(Self {
-            storage,
-            frame_size_limit,
-            is_initiator: false,
-            last_timestamp_in: 0,
-            last_timestamp_out: 0,
-        })
+        Self::with_hasher(storage, Sha256, frame_size_limit)
     }
 
     /// Create new [`Negentropy`] instance from owned storage
@@ -89,6 +140,48 @@ where
         Self::new(Storage::Borrowed(storage), frame_size_limit)
     }
 
+    /// Create new [`Negentropy`] instance from shared storage
+    ///
+    /// Frame size limit must be `equal to 0` or `greater than 4096`
+    pub fn shared(storage: Arc<T>, frame_size_limit: u64) -> Result<Self, Error> {
+        Self::new(Storage::Shared(storage), frame_size_limit)
+    }
+}
+
+impl<'a, T, H, const N: usize> Negentropy<'a, T, H, N>
+where
+    T: NegentropyStorageBase<N>,
+    H: FingerprintHasher,
+{
+    /// Create new [`Negentropy`] instance with a custom fingerprint hasher
+    ///
+    /// Both peers must use the same hasher: see the [`hasher`] module.
+    ///
+    /// Frame size limit must be `equal to 0` or `greater than 4096`
+    pub fn with_hasher(
+        storage: Storage<'a, T>,
+        hasher: H,
+        frame_size_limit: u64,
+    ) -> Result<Self, Error> {
+        if frame_size_limit != 0 && frame_size_limit < 4096 {
+            return Err(Error::FrameSizeLimitTooSmall);
+        }
+
+        Ok(Self {
+            storage,
+            hasher,
+            frame_size_limit,
+            is_initiator: false,
+            supported_versions: SupportedVersions::default(),
+            protocol_version: PROTOCOL_VERSION,
+            #[cfg(feature = "experimental-id-prefix")]
+            id_prefix_len: None,
+            last_timestamp_in: 0,
+            last_timestamp_out: 0,
+            reported_need_ids: Default::default(),
+        })
+    }
+
     /// Initiate reconciliation set
     pub fn initiate(&mut self) -> Result<Vec<u8>, Error> {
         if self.is_initiator {
@@ -96,8 +189,17 @@ where
         }
         self.is_initiator = true;
 
+        self.protocol_version = self
+            .supported_versions
+            .highest()
+            .ok_or(Error::NoSupportedVersions)?;
+
+        self.initial_message()
+    }
+
+    fn initial_message(&mut self) -> Result<Vec<u8>, Error> {
         let mut output: Vec<u8> = Vec::new();
-        output.push(PROTOCOL_VERSION as u8);
+        output.push(self.protocol_version as u8);
 
         output.extend(self.split_range(0, self.storage.size()?, Bound::with_timestamp(MAX_U64))?);
 
@@ -114,6 +216,37 @@ where
         self.is_initiator = true;
     }
 
+    /// Get the supported-version mask
+    pub fn supported_versions(&self) -> SupportedVersions {
+        self.supported_versions
+    }
+
+    /// Set the supported-version mask
+    ///
+    /// Must be called before [`Negentropy::initiate`].
+    /// Fails with [`Error::NoSupportedVersions`] if `versions` is empty.
+    pub fn set_supported_versions(&mut self, versions: SupportedVersions) -> Result<(), Error> {
+        if self.is_initiator {
+            return Err(Error::AlreadyBuiltInitialMessage);
+        }
+
+        if versions.is_empty() {
+            return Err(Error::NoSupportedVersions);
+        }
+
+        self.supported_versions = versions;
+
+        Ok(())
+    }
+
+    /// Get the protocol version in use
+    ///
+    /// For the initiator, this is the proposed version until the first response has been reconciled;
+    /// for the non-initiator, the version of the last reconciled message.
+    pub fn protocol_version(&self) -> u64 {
+        self.protocol_version
+    }
+
     /// Reconcile (server method)
     pub fn reconcile(&mut self, query: &[u8]) -> Result<Vec<u8>, Error> {
         if self.is_initiator {
@@ -123,12 +256,36 @@ where
         self.reconcile_aux(query, &mut Vec::new(), &mut Vec::new())
     }
 
+    /// Reconcile (server method), reporting what was learned about the client's set
+    ///
+    /// * `have_ids`: IDs we have that the client is known to lack
+    /// * `need_ids`: IDs the client sent that we don't have
+    ///
+    /// Only ranges for which the client sent its full ID list are reported, so the result may be
+    /// a subset of the actual difference (the client is always the one learning the full difference).
+    ///
+    /// With a frame size limit, our ID list for a range may be truncated: `have_ids` only gets the
+    /// IDs we sent, while `need_ids` gets all the client's ones for the range, which are remembered
+    /// so that they are not reported again when the deferred part of the range is reconciled.
+    pub fn reconcile_with_diff(
+        &mut self,
+        query: &[u8],
+        have_ids: &mut Vec<Id<N>>,
+        need_ids: &mut Vec<Id<N>>,
+    ) -> Result<Vec<u8>, Error> {
+        if self.is_initiator {
+            return Err(Error::Initiator);
+        }
+
+        self.reconcile_aux(query, have_ids, need_ids)
+    }
+
     /// Reconcile (client method)
     pub fn reconcile_with_ids(
         &mut self,
         query: &[u8],
-        have_ids: &mut Vec<Id>,
-        need_ids: &mut Vec<Id>,
+        have_ids: &mut Vec<Id<N>>,
+        need_ids: &mut Vec<Id<N>>,
     ) -> Result<Option<Vec<u8>>, Error> {
         if !self.is_initiator {
             return Err(Error::NonInitiator);
@@ -145,15 +302,12 @@ where
     fn reconcile_aux(
         &mut self,
         mut query: &[u8],
-        have_ids: &mut Vec<Id>,
-        need_ids: &mut Vec<Id>,
+        have_ids: &mut Vec<Id<N>>,
+        need_ids: &mut Vec<Id<N>>,
     ) -> Result<Vec<u8>, Error> {
         self.last_timestamp_in = 0;
         self.last_timestamp_out = 0;
 
-        let mut full_output: Vec<u8> = Vec::with_capacity(1);
-        full_output.push(PROTOCOL_VERSION as u8);
-
         let protocol_version: u64 = get_byte_array::<1>(&mut query)?
             .first()
             .copied()
@@ -164,29 +318,92 @@ where
             return Err(Error::InvalidProtocolVersion);
         }
 
-        if protocol_version != PROTOCOL_VERSION {
-            if self.is_initiator {
-                return Err(Error::UnsupportedProtocolVersion);
-            } else {
-                return Ok(full_output);
+        if self.is_initiator {
+            if protocol_version != self.protocol_version {
+                // The server doesn't support our version and replied with its highest one:
+                // restart with the highest version we support, not greater than that one
+                let retry_version: Option<u64> = self
+                    .supported_versions
+                    .highest_below(core::cmp::min(protocol_version + 1, self.protocol_version));
+
+                return match retry_version {
+                    Some(version) => {
+                        self.protocol_version = version;
+                        self.initial_message()
+                    }
+                    None => Err(Error::UnsupportedProtocolVersion),
+                };
             }
+        } else if self.supported_versions.contains(protocol_version) {
+            self.protocol_version = protocol_version;
+        } else {
+            let highest: u64 = self
+                .supported_versions
+                .highest()
+                .ok_or(Error::NoSupportedVersions)?;
+            return Ok(vec![highest as u8]);
         }
 
+        let mut full_output: Vec<u8> = Vec::with_capacity(1);
+        full_output.push(self.protocol_version as u8);
+
         let storage_size = self.storage.size()?;
-        let mut prev_bound: Bound = Bound::new();
+        let mut prev_bound: Bound<N> = Bound::new();
         let mut prev_index: usize = 0;
         let mut skip: bool = false;
 
+        // Set when the frame size limit is reached: the remaining ranges are deferred to the next round
+        let mut truncated: bool = false;
+        // Start of the pending deferred range (index, lower bound and whether a skip precedes it)
+        let mut deferred: Option<(usize, Bound<N>, bool)> = None;
+        // Index up to which the output covers the ranges
+        let mut emitted_index: usize = 0;
+
         while !query.is_empty() {
             let mut o: Vec<u8> = Vec::new();
+            let skipped: bool = skip;
+            let last_timestamp_out: u64 = self.last_timestamp_out;
 
-            let curr_bound: Bound = self.decode_bound(&mut query)?;
+            let curr_bound: Bound<N> = self.decode_bound(&mut query)?;
             let mode: Mode = self.decode_mode(&mut query)?;
 
             let lower: usize = prev_index;
-            let mut upper: usize =
-                self.storage
-                    .find_lower_bound(prev_index, storage_size, &curr_bound);
+            let upper: usize = self
+                .storage
+                .find_lower_bound(prev_index, storage_size, &curr_bound);
+
+            if truncated {
+                self.skip_mode_payload(&mut query, mode)?;
+
+                if mode != Mode::Skip {
+                    // Defer the range, merging it with the adjacent deferred ones
+                    if deferred.is_none() {
+                        deferred = Some((lower, prev_bound, skip));
+                        skip = false;
+                    }
+                } else if let Some((deferred_index, deferred_bound, deferred_skip)) =
+                    deferred.take()
+                {
+                    if !self.encode_deferred_range(
+                        &mut full_output,
+                        deferred_index,
+                        lower,
+                        deferred_skip.then(|| deferred_bound),
+                        prev_bound,
+                    )? {
+                        return self.encode_remaining_range(full_output, emitted_index);
+                    }
+
+                    emitted_index = lower;
+                    skip = true;
+                } else {
+                    skip = true;
+                }
+
+                prev_index = upper;
+                prev_bound = curr_bound;
+                continue;
+            }
 
             match mode {
                 Mode::Skip => {
@@ -194,8 +411,10 @@ where
                 }
                 Mode::Fingerprint => {
                     let their_fingerprint: [u8; FINGERPRINT_SIZE] = get_byte_array(&mut query)?;
-                    let our_fingerprint: [u8; FINGERPRINT_SIZE] =
-                        self.storage.fingerprint(lower, upper)?.to_bytes();
+                    let our_fingerprint: [u8; FINGERPRINT_SIZE] = self
+                        .storage
+                        .fingerprint(&self.hasher, lower, upper)?
+                        .to_bytes();
 
                     if their_fingerprint != our_fingerprint {
                         // do_skip
@@ -214,34 +433,29 @@ where
                     let num_ids: u64 = decode_var_int(&mut query)?;
 
                     #[cfg(feature = "std")]
-                    let mut their_elems: HashSet<Id> = HashSet::with_capacity(num_ids as usize);
+                    let mut their_elems: HashSet<Id<N>> = HashSet::with_capacity(num_ids as usize);
                     #[cfg(not(feature = "std"))]
-                    let mut their_elems: BTreeSet<Id> = BTreeSet::new();
+                    let mut their_elems: BTreeSet<Id<N>> = BTreeSet::new();
 
                     for _ in 0..num_ids {
-                        let e: [u8; ID_SIZE] = get_byte_array(&mut query)?;
+                        let e: [u8; N] = get_byte_array(&mut query)?;
                         their_elems.insert(Id::from_byte_array(e));
                     }
 
-                    self.storage.iterate(lower, upper, &mut |item: Item, _| {
-                        let k: Id = item.id;
-                        if !their_elems.contains(&k) {
-                            if self.is_initiator {
-                                have_ids.push(k);
-                            }
-                        } else {
-                            their_elems.remove(&k);
-                        }
+                    if self.is_initiator {
+                        self.storage
+                            .iterate(lower, upper, &mut |item: Item<N>, _| {
+                                let k: Id<N> = item.id;
+                                if !their_elems.remove(&k) {
+                                    have_ids.push(k);
+                                }
 
-                        Ok(true)
-                    })?;
+                                Ok(true)
+                            })?;
 
-                    if self.is_initiator {
-                        skip = true;
+                        need_ids.extend(their_elems);
 
-                        for k in their_elems.into_iter() {
-                            need_ids.push(k);
-                        }
+                        skip = true;
                     } else {
                         // do_skip
                         if skip {
@@ -250,73 +464,183 @@ where
                             o.extend(self.encode_mode(Mode::Skip));
                         }
 
-                        let mut response_ids: Vec<u8> = Vec::new();
-                        let mut num_response_ids: usize = 0;
-                        let mut end_bound = curr_bound;
+                        let (response, end_index) = self.encode_id_list_response(
+                            lower,
+                            upper,
+                            curr_bound,
+                            full_output.len(),
+                        )?;
+                        o.extend(response);
+
+                        full_output.extend(&o);
+                        o.clear();
+                        emitted_index = end_index;
 
+                        // Only the items we sent are known to be missing on the client:
+                        // the deferred ones are reported once their range is reconciled again
                         self.storage
-                            .iterate(lower, upper, &mut |item: Item, index| {
-                                if self.exceeded_frame_size_limit(
-                                    full_output.len() + response_ids.len(),
-                                ) {
-                                    end_bound = Bound::from_item(&item);
-                                    upper = index; // shrink upper so that remaining range gets correct fingerprint
-                                    return Ok(false);
+                            .iterate(lower, upper, &mut |item: Item<N>, index| {
+                                let k: Id<N> = item.id;
+                                if !their_elems.remove(&k) && index < end_index {
+                                    have_ids.push(k);
                                 }
 
-                                response_ids.extend(item.id.iter());
-                                num_response_ids += 1;
                                 Ok(true)
                             })?;
 
-                        o.extend(self.encode_bound(&end_bound));
-                        o.extend(self.encode_mode(Mode::IdList));
-                        o.extend(encode_var_int(num_response_ids as u64));
-                        o.extend(response_ids);
+                        self.report_need_ids(their_elems, end_index < upper, need_ids);
 
-                        full_output.extend(&o);
-                        o.clear();
+                        if end_index < upper {
+                            // Response truncated: defer the rest of the range
+                            truncated = true;
+                            deferred = Some((end_index, prev_bound, false));
+                        }
+                    }
+                }
+                #[cfg(feature = "experimental-id-prefix")]
+                Mode::IdPrefixList => {
+                    if self.is_initiator {
+                        return Err(Error::UnexpectedMode(mode.as_u64()));
+                    }
+
+                    // do_skip
+                    if skip {
+                        skip = false;
+                        o.extend(self.encode_bound(&prev_bound));
+                        o.extend(self.encode_mode(Mode::Skip));
+                    }
+
+                    let end_index: usize = match self.encode_id_prefix_diff(
+                        &mut query,
+                        lower,
+                        upper,
+                        curr_bound,
+                        full_output.len(),
+                        have_ids,
+                    )? {
+                        Some(diff) => {
+                            o.extend(diff);
+                            upper
+                        }
+                        // Prefix collision or frame size limit: fall back to our full ID list
+                        None => {
+                            let (response, end_index) = self.encode_id_list_response(
+                                lower,
+                                upper,
+                                curr_bound,
+                                full_output.len(),
+                            )?;
+                            o.extend(response);
+                            end_index
+                        }
+                    };
+
+                    full_output.extend(&o);
+                    o.clear();
+                    emitted_index = end_index;
+
+                    if end_index < upper {
+                        // Response truncated: defer the rest of the range
+                        truncated = true;
+                        deferred = Some((end_index, prev_bound, false));
+                    }
+                }
+                #[cfg(feature = "experimental-id-prefix")]
+                Mode::IdPrefixDiff => {
+                    if !self.is_initiator {
+                        return Err(Error::UnexpectedMode(mode.as_u64()));
+                    }
+
+                    if self.process_id_prefix_diff(&mut query, lower, upper, have_ids, need_ids)? {
+                        skip = true;
+                    } else {
+                        // Fingerprint of the matched items differs (prefix collision across the peers):
+                        // ask again with our full ID list
+                        if skip {
+                            skip = false;
+                            o.extend(self.encode_bound(&prev_bound));
+                            o.extend(self.encode_mode(Mode::Skip));
+                        }
+
+                        o.extend(self.encode_id_list(lower, upper, curr_bound)?);
                     }
                 }
             }
 
-            if self.exceeded_frame_size_limit(full_output.len() + o.len()) {
-                // frameSizeLimit exceeded: Stop range processing and return a fingerprint for the remaining range
-                let remaining_fingerprint = self.storage.fingerprint(upper, storage_size)?;
+            if !truncated && self.exceeded_frame_size_limit(full_output.len() + o.len()) {
+                // frameSizeLimit exceeded: Stop answering ranges and defer the remaining ones to the next round
+                truncated = true;
 
-                full_output.extend(self.encode_bound(&Bound::with_timestamp(MAX_U64)));
-                full_output.extend(self.encode_mode(Mode::Fingerprint));
-                full_output.extend(remaining_fingerprint.iter());
-                break;
-            } else {
+                if !o.is_empty() {
+                    // Drop the output of the current range, restoring the timestamp encoding state
+                    self.last_timestamp_out = last_timestamp_out;
+                    deferred = Some((lower, prev_bound, skipped));
+                    skip = false;
+                }
+            } else if !o.is_empty() {
                 full_output.extend(o);
+                emitted_index = upper;
             }
 
             prev_index = upper;
             prev_bound = curr_bound;
         }
 
+        if let Some((deferred_index, deferred_bound, deferred_skip)) = deferred {
+            if !self.encode_deferred_range(
+                &mut full_output,
+                deferred_index,
+                prev_index,
+                deferred_skip.then(|| deferred_bound),
+                prev_bound,
+            )? {
+                return self.encode_remaining_range(full_output, emitted_index);
+            }
+        }
+
         Ok(full_output)
     }
 
+    /// Report the IDs the client sent for a range that we don't have
+    ///
+    /// The client's IDs carry no timestamp, so the ones in the deferred part of a truncated range
+    /// can't be told apart: they are all reported, and remembered to not report them again when the
+    /// client sends them back for the deferred range.
+    fn report_need_ids<I>(&mut self, ids: I, truncated: bool, need_ids: &mut Vec<Id<N>>)
+    where
+        I: IntoIterator<Item = Id<N>>,
+    {
+        for id in ids.into_iter() {
+            let reported: bool = if truncated {
+                !self.reported_need_ids.insert(id)
+            } else {
+                self.reported_need_ids.remove(&id)
+            };
+
+            if !reported {
+                need_ids.push(id);
+            }
+        }
+    }
+
     fn split_range(
         &mut self,
         lower: usize,
         upper: usize,
-        upper_bound: Bound,
+        upper_bound: Bound<N>,
     ) -> Result<Vec<u8>, Error> {
         let num_elems: usize = upper - lower;
         let mut o: Vec<u8> = Vec::with_capacity(10 + 10 + num_elems);
 
         if num_elems < DOUBLE_BUCKETS {
-            o.extend(self.encode_bound(&upper_bound));
-            o.extend(self.encode_mode(Mode::IdList));
+            #[cfg(feature = "experimental-id-prefix")]
+            if self.is_initiator {
+                if let Some(list) = self.encode_id_prefix_list(lower, upper, upper_bound)? {
+                    return Ok(list);
+                }
+            }
 
-            o.extend(encode_var_int(num_elems as u64));
-            self.storage.iterate(lower, upper, &mut |item: Item, _| {
-                o.extend(item.id.iter());
-                Ok(true)
-            })?;
+            o.extend(self.encode_id_list(lower, upper, upper_bound)?);
         } else {
             let items_per_bucket: usize = num_elems / BUCKETS;
             let buckets_with_extra: usize = num_elems % BUCKETS;
@@ -325,17 +649,19 @@ where
             for i in 0..BUCKETS {
                 let bucket_size: usize =
                     items_per_bucket + (if i < buckets_with_extra { 1 } else { 0 });
-                let our_fingerprint = self.storage.fingerprint(curr, curr + bucket_size)?;
+                let our_fingerprint =
+                    self.storage
+                        .fingerprint(&self.hasher, curr, curr + bucket_size)?;
                 curr += bucket_size;
 
                 let next_bound = if curr == upper {
                     upper_bound
                 } else {
-                    let mut prev_item: Item = Item::with_timestamp(0);
-                    let mut curr_item: Item = Item::with_timestamp(0);
+                    let mut prev_item: Item<N> = Item::with_timestamp(0);
+                    let mut curr_item: Item<N> = Item::with_timestamp(0);
 
                     self.storage
-                        .iterate(curr - 1, curr + 1, &mut |item: Item, index| {
+                        .iterate(curr - 1, curr + 1, &mut |item: Item<N>, index| {
                             if index == curr - 1 {
                                 prev_item = item;
                             } else {
@@ -357,6 +683,139 @@ where
         Ok(o)
     }
 
+    fn encode_id_list(
+        &mut self,
+        lower: usize,
+        upper: usize,
+        upper_bound: Bound<N>,
+    ) -> Result<Vec<u8>, Error> {
+        let mut o: Vec<u8> = Vec::with_capacity(10 + 10 + (upper - lower) * N);
+
+        o.extend(self.encode_bound(&upper_bound));
+        o.extend(self.encode_mode(Mode::IdList));
+
+        o.extend(encode_var_int((upper - lower) as u64));
+        self.storage
+            .iterate(lower, upper, &mut |item: Item<N>, _| {
+                o.extend(item.id.iter());
+                Ok(true)
+            })?;
+
+        Ok(o)
+    }
+
+    /// Our full ID list for the range, truncated when the frame size limit is reached
+    ///
+    /// Returns the list and the index where it ends.
+    fn encode_id_list_response(
+        &mut self,
+        lower: usize,
+        upper: usize,
+        curr_bound: Bound<N>,
+        output_len: usize,
+    ) -> Result<(Vec<u8>, usize), Error> {
+        let mut o: Vec<u8> = Vec::new();
+        let mut response_ids: Vec<u8> = Vec::new();
+        let mut end_index: usize = upper;
+        let mut end_bound = curr_bound;
+
+        self.storage
+            .iterate(lower, upper, &mut |item: Item<N>, index| {
+                if self.exceeded_frame_size_limit(output_len + response_ids.len()) {
+                    end_bound = Bound::from_item(&item);
+                    end_index = index;
+                    return Ok(false);
+                }
+
+                response_ids.extend(item.id.iter());
+                Ok(true)
+            })?;
+
+        o.extend(self.encode_bound(&end_bound));
+        o.extend(self.encode_mode(Mode::IdList));
+        o.extend(encode_var_int((end_index - lower) as u64));
+        o.extend(response_ids);
+
+        Ok((o, end_index))
+    }
+
+    /// Skip the payload of a range that is deferred to the next round
+    fn skip_mode_payload(&self, query: &mut &[u8], mode: Mode) -> Result<(), Error> {
+        match mode {
+            Mode::Skip => {}
+            Mode::Fingerprint => {
+                get_bytes(query, FINGERPRINT_SIZE)?;
+            }
+            Mode::IdList => {
+                let num_ids: u64 = decode_var_int(query)?;
+                let len: usize = (num_ids as usize)
+                    .checked_mul(N)
+                    .ok_or(Error::ParseEndsPrematurely)?;
+                get_bytes(query, len)?;
+            }
+            #[cfg(feature = "experimental-id-prefix")]
+            Mode::IdPrefixList | Mode::IdPrefixDiff => {
+                self.skip_id_prefix_payload(query, mode)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Append our fingerprint for a deferred range, preceded by a skip (up to `skip_bound`) if any
+    ///
+    /// Returns `false`, leaving the output untouched, if it doesn't fit in the frame.
+    fn encode_deferred_range(
+        &mut self,
+        full_output: &mut Vec<u8>,
+        lower: usize,
+        upper: usize,
+        skip_bound: Option<Bound<N>>,
+        upper_bound: Bound<N>,
+    ) -> Result<bool, Error> {
+        let last_timestamp_out: u64 = self.last_timestamp_out;
+        let mut o: Vec<u8> = Vec::new();
+
+        if let Some(skip_bound) = skip_bound {
+            o.extend(self.encode_bound(&skip_bound));
+            o.extend(self.encode_mode(Mode::Skip));
+        }
+
+        o.extend(self.encode_bound(&upper_bound));
+        o.extend(self.encode_mode(Mode::Fingerprint));
+        o.extend(self.storage.fingerprint(&self.hasher, lower, upper)?.iter());
+
+        // Keep room for the remaining range fingerprint
+        if self.frame_size_limit != 0
+            && (full_output.len() + o.len() + REMAINING_RANGE_SIZE) as u64 > self.frame_size_limit
+        {
+            self.last_timestamp_out = last_timestamp_out;
+            return Ok(false);
+        }
+
+        full_output.extend(o);
+
+        Ok(true)
+    }
+
+    /// Terminate the output with our fingerprint for everything from `lower` on
+    fn encode_remaining_range(
+        &mut self,
+        mut full_output: Vec<u8>,
+        lower: usize,
+    ) -> Result<Vec<u8>, Error> {
+        let storage_size: usize = self.storage.size()?;
+        let remaining_fingerprint = self
+            .storage
+            .fingerprint(&self.hasher, lower, storage_size)?;
+
+        full_output.extend(self.encode_bound(&Bound::with_timestamp(MAX_U64)));
+        full_output.extend(self.encode_mode(Mode::Fingerprint));
+        full_output.extend(remaining_fingerprint.iter());
+
+        Ok(full_output)
+    }
+
     fn exceeded_frame_size_limit(&self, n: usize) -> bool {
         self.frame_size_limit != 0 && n > (self.frame_size_limit as usize) - 200
     }
@@ -365,7 +824,15 @@ where
 
     fn decode_mode(&self, encoded: &mut &[u8]) -> Result<Mode, Error> {
         let mode = decode_var_int(encoded)?;
-        Mode::try_from(mode)
+        let mode = Mode::try_from(mode)?;
+
+        // Extension modes are only valid within the extension protocol version
+        #[cfg(feature = "experimental-id-prefix")]
+        if mode.is_id_prefix() && self.protocol_version != ID_PREFIX_PROTOCOL_VERSION {
+            return Err(Error::UnexpectedMode(mode.as_u64()));
+        }
+
+        Ok(mode)
     }
 
     fn decode_timestamp_in(&mut self, encoded: &mut &[u8]) -> Result<u64, Error> {
@@ -380,7 +847,7 @@ where
         Ok(timestamp)
     }
 
-    fn decode_bound(&mut self, encoded: &mut &[u8]) -> Result<Bound, Error> {
+    fn decode_bound(&mut self, encoded: &mut &[u8]) -> Result<Bound<N>, Error> {
         let timestamp = self.decode_timestamp_in(encoded)?;
         let len: usize = decode_var_int(encoded)? as usize;
         let id: &[u8] = get_bytes(encoded, len)?;
@@ -404,7 +871,7 @@ where
         encode_var_int(timestamp.saturating_add(1))
     }
 
-    fn encode_bound(&mut self, bound: &Bound) -> Vec<u8> {
+    fn encode_bound(&mut self, bound: &Bound<N>) -> Vec<u8> {
         let mut output: Vec<u8> = Vec::new();
 
         output.extend(self.encode_timestamp_out(bound.item.timestamp));
@@ -417,7 +884,7 @@ where
         output
     }
 
-    fn get_minimal_bound(&self, prev: &Item, curr: &Item) -> Result<Bound, Error> {
+    fn get_minimal_bound(&self, prev: &Item<N>, curr: &Item<N>) -> Result<Bound<N>, Error> {
         if curr.timestamp != prev.timestamp {
             Ok(Bound::with_timestamp(curr.timestamp))
         } else {
@@ -425,7 +892,7 @@ where
             let curr_key = curr.id;
             let prev_key = prev.id;
 
-            for i in 0..ID_SIZE {
+            for i in 0..N {
                 if curr_key[i] != prev_key[i] {
                     break;
                 }
@@ -577,6 +1044,647 @@ mod tests {
             ]
         )
     }
+
+    #[test]
+    fn test_reconciliation_server_diff() {
+        // Client
+        let mut storage_client = NegentropyStorageVector::new();
+        storage_client
+            .insert(0, Id::from_byte_array([0xaa; 32]))
+            .unwrap();
+        storage_client
+            .insert(1, Id::from_byte_array([0xbb; 32]))
+            .unwrap();
+        storage_client.seal().unwrap();
+
+        let mut client = Negentropy::borrowed(&storage_client, 0).unwrap();
+        let init_output = client.initiate().unwrap();
+
+        // Relay
+        let mut storage_relay = NegentropyStorageVector::new();
+        storage_relay
+            .insert(0, Id::from_byte_array([0xaa; 32]))
+            .unwrap();
+        storage_relay
+            .insert(2, Id::from_byte_array([0xcc; 32]))
+            .unwrap();
+        storage_relay
+            .insert(3, Id::from_byte_array([0x11; 32]))
+            .unwrap();
+        storage_relay.seal().unwrap();
+
+        let mut relay = Negentropy::borrowed(&storage_relay, 0).unwrap();
+        let mut relay_have_ids = Vec::new();
+        let mut relay_need_ids = Vec::new();
+        relay
+            .reconcile_with_diff(&init_output, &mut relay_have_ids, &mut relay_need_ids)
+            .unwrap();
+
+        // The client sent its whole set as an ID list: the relay knows the full difference
+        relay_have_ids.sort();
+        assert_eq!(
+            relay_have_ids,
+            vec![
+                Id::from_byte_array([0x11; 32]),
+                Id::from_byte_array([0xcc; 32])
+            ]
+        );
+        assert_eq!(relay_need_ids, vec![Id::from_byte_array([0xbb; 32])]);
+
+        // Server method only
+        assert_eq!(
+            client.reconcile_with_diff(&init_output, &mut Vec::new(), &mut Vec::new()),
+            Err(Error::Initiator)
+        );
+    }
+
+    /// Sync the client with the relay, returning the relay's diff and the client's one
+    fn sync_with_diff(
+        client_ids: &[Id],
+        relay_ids: &[Id],
+        frame_size_limit: u64,
+    ) -> (Vec<Id>, Vec<Id>, Vec<Id>, Vec<Id>) {
+        // Client
+        let mut storage_client = NegentropyStorageVector::new();
+        for id in client_ids.iter() {
+            storage_client.insert(0, *id).unwrap();
+        }
+        storage_client.seal().unwrap();
+
+        let mut client = Negentropy::borrowed(&storage_client, frame_size_limit).unwrap();
+        let mut msg = client.initiate().unwrap();
+
+        // Relay
+        let mut storage_relay = NegentropyStorageVector::new();
+        for id in relay_ids.iter() {
+            storage_relay.insert(0, *id).unwrap();
+        }
+        storage_relay.seal().unwrap();
+
+        let mut relay = Negentropy::borrowed(&storage_relay, frame_size_limit).unwrap();
+
+        let mut have_ids = Vec::new();
+        let mut need_ids = Vec::new();
+        let mut relay_have_ids = Vec::new();
+        let mut relay_need_ids = Vec::new();
+        loop {
+            let response = relay
+                .reconcile_with_diff(&msg, &mut relay_have_ids, &mut relay_need_ids)
+                .unwrap();
+            match client
+                .reconcile_with_ids(&response, &mut have_ids, &mut need_ids)
+                .unwrap()
+            {
+                Some(next) => msg = next,
+                None => break,
+            }
+        }
+
+        (relay_have_ids, relay_need_ids, have_ids, need_ids)
+    }
+
+    fn id_from_u16(i: u16) -> Id {
+        let mut id = [0u8; 32];
+        id[..2].copy_from_slice(&i.to_be_bytes());
+        Id::from_byte_array(id)
+    }
+
+    fn is_deduped(ids: &[Id]) -> bool {
+        let mut sorted = ids.to_vec();
+        sorted.sort();
+        sorted.dedup();
+        sorted.len() == ids.len()
+    }
+
+    #[test]
+    fn test_reconciliation_server_diff_is_subset() {
+        let client_ids: Vec<Id> = (0..2000u16)
+            .filter(|i| i % 2 == 0)
+            .map(id_from_u16)
+            .collect();
+        let relay_ids: Vec<Id> = (0..2000u16)
+            .filter(|i| i % 3 == 0)
+            .map(id_from_u16)
+            .collect();
+
+        for frame_size_limit in [0, 4097, 5000, 20000] {
+            let (relay_have_ids, relay_need_ids, have_ids, need_ids) =
+                sync_with_diff(&client_ids, &relay_ids, frame_size_limit);
+
+            assert!(!relay_have_ids.is_empty());
+            assert!(!relay_need_ids.is_empty());
+            assert!(is_deduped(&relay_have_ids));
+            assert!(is_deduped(&relay_need_ids));
+            assert!(relay_have_ids.iter().all(|id| need_ids.contains(id)));
+            assert!(relay_need_ids.iter().all(|id| have_ids.contains(id)));
+        }
+    }
+
+    #[test]
+    fn test_reconciliation_server_diff_frame_size_limit() {
+        // The client always sends its full ID list, while the relay's one doesn't fit in a frame
+        let client_ids: Vec<Id> = (0..1500u16)
+            .filter(|i| i % 100 == 0 || i % 100 == 1)
+            .map(id_from_u16)
+            .collect();
+        let relay_ids: Vec<Id> = (0..1500u16)
+            .filter(|i| i % 100 != 1)
+            .map(id_from_u16)
+            .collect();
+
+        for frame_size_limit in [0, 4097, 5000, 20000] {
+            let (mut relay_have_ids, mut relay_need_ids, mut have_ids, mut need_ids) =
+                sync_with_diff(&client_ids, &relay_ids, frame_size_limit);
+
+            assert!(is_deduped(&relay_have_ids));
+            assert!(is_deduped(&relay_need_ids));
+
+            relay_have_ids.sort();
+            relay_need_ids.sort();
+            have_ids.sort();
+            need_ids.sort();
+            assert_eq!(relay_have_ids.len(), 1470);
+            assert_eq!(relay_have_ids, need_ids);
+            assert_eq!(relay_need_ids.len(), 15);
+            assert_eq!(relay_need_ids, have_ids);
+        }
+    }
+
+    fn reconcile_id_size<const N: usize>() {
+        let id = |i: u8| {
+            let mut id = [i; N];
+            // Make IDs share a long prefix, to exercise minimal bounds
+            id[..N - 1].copy_from_slice(&[0x11; N][..N - 1]);
+            Id::from_byte_array(id)
+        };
+
+        // Client
+        let mut storage_client = NegentropyStorageVector::<N>::default();
+        for i in 0..100u8 {
+            storage_client.insert(0, id(i)).unwrap();
+        }
+        storage_client.seal().unwrap();
+
+        let mut client = Negentropy::borrowed(&storage_client, 0).unwrap();
+        let mut msg = client.initiate().unwrap();
+
+        // Relay
+        let mut storage_relay = NegentropyStorageVector::<N>::default();
+        for i in 50..200u8 {
+            storage_relay.insert(0, id(i)).unwrap();
+        }
+        storage_relay.seal().unwrap();
+
+        let mut relay = Negentropy::borrowed(&storage_relay, 0).unwrap();
+
+        let mut have_ids = Vec::new();
+        let mut need_ids = Vec::new();
+        loop {
+            let response = relay.reconcile(&msg).unwrap();
+            match client
+                .reconcile_with_ids(&response, &mut have_ids, &mut need_ids)
+                .unwrap()
+            {
+                Some(next) => msg = next,
+                None => break,
+            }
+        }
+
+        have_ids.sort();
+        need_ids.sort();
+        assert_eq!(have_ids, (0..50u8).map(id).collect::<Vec<_>>());
+        assert_eq!(need_ids, (100..200u8).map(id).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_reconciliation_set_id_sizes() {
+        reconcile_id_size::<16>();
+        reconcile_id_size::<20>();
+        reconcile_id_size::<32>();
+        reconcile_id_size::<64>();
+    }
+
+    #[cfg(feature = "blake3")]
+    #[test]
+    fn test_reconciliation_set_with_hasher() {
+        use crate::hasher::Blake3Keyed;
+
+        let key = [0x42; 32];
+
+        // Client
+        let mut storage_client = NegentropyStorageVector::new();
+        for i in 0..100u8 {
+            storage_client
+                .insert(i as u64, Id::from_byte_array([i; 32]))
+                .unwrap();
+        }
+        storage_client.seal().unwrap();
+
+        let mut client =
+            Negentropy::with_hasher(Storage::Borrowed(&storage_client), Blake3Keyed::new(key), 0)
+                .unwrap();
+        let mut msg = client.initiate().unwrap();
+
+        // Relay
+        let mut storage_relay = NegentropyStorageVector::new();
+        for i in 50..200u8 {
+            storage_relay
+                .insert(i as u64, Id::from_byte_array([i; 32]))
+                .unwrap();
+        }
+        storage_relay.seal().unwrap();
+
+        let mut relay =
+            Negentropy::with_hasher(Storage::Borrowed(&storage_relay), Blake3Keyed::new(key), 0)
+                .unwrap();
+
+        let mut have_ids = Vec::new();
+        let mut need_ids = Vec::new();
+        loop {
+            let response = relay.reconcile(&msg).unwrap();
+            match client
+                .reconcile_with_ids(&response, &mut have_ids, &mut need_ids)
+                .unwrap()
+            {
+                Some(next) => msg = next,
+                None => break,
+            }
+        }
+
+        have_ids.sort();
+        need_ids.sort();
+        assert_eq!(
+            have_ids,
+            (0..50u8)
+                .map(|i| Id::from_byte_array([i; 32]))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            need_ids,
+            (100..200u8)
+                .map(|i| Id::from_byte_array([i; 32]))
+                .collect::<Vec<_>>()
+        );
+    }
+    #[test]
+    fn test_reconciliation_frame_size_limit() {
+        let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut next = || {
+            // xorshift64
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        let mut storage_client = NegentropyStorageVector::new();
+        let mut storage_relay = NegentropyStorageVector::new();
+        for _ in 0..6_000 {
+            let mut id = [0u8; 32];
+            for chunk in id.chunks_exact_mut(8) {
+                chunk.copy_from_slice(&next().to_le_bytes());
+            }
+            let timestamp = next() % 100_000;
+            match next() % 3 {
+                0 => storage_client
+                    .insert(timestamp, Id::from_byte_array(id))
+                    .unwrap(),
+                1 => storage_relay
+                    .insert(timestamp, Id::from_byte_array(id))
+                    .unwrap(),
+                _ => {
+                    storage_client
+                        .insert(timestamp, Id::from_byte_array(id))
+                        .unwrap();
+                    storage_relay
+                        .insert(timestamp, Id::from_byte_array(id))
+                        .unwrap();
+                }
+            }
+        }
+        storage_client.seal().unwrap();
+        storage_relay.seal().unwrap();
+
+        let sync = |client_limit: u64, relay_limit: u64| {
+            let mut client = Negentropy::borrowed(&storage_client, client_limit).unwrap();
+            let mut relay = Negentropy::borrowed(&storage_relay, relay_limit).unwrap();
+
+            let mut have_ids = Vec::new();
+            let mut need_ids = Vec::new();
+            let mut rounds: usize = 0;
+            let mut msg = client.initiate().unwrap();
+            loop {
+                assert!(client_limit == 0 || msg.len() as u64 <= client_limit);
+                let response = relay.reconcile(&msg).unwrap();
+                assert!(relay_limit == 0 || response.len() as u64 <= relay_limit);
+                rounds += 1;
+                match client
+                    .reconcile_with_ids(&response, &mut have_ids, &mut need_ids)
+                    .unwrap()
+                {
+                    Some(next) => msg = next,
+                    None => break,
+                }
+            }
+
+            have_ids.sort();
+            need_ids.sort();
+            (have_ids, need_ids, rounds)
+        };
+
+        let (have_ids, need_ids, rounds) = sync(0, 0);
+        assert_eq!(have_ids.len(), 6_000 - storage_relay.size().unwrap());
+        assert_eq!(need_ids.len(), 6_000 - storage_client.size().unwrap());
+
+        for frame_size_limit in [4096, 5000, 10_000, 65_536] {
+            for (client_limit, relay_limit) in [
+                (frame_size_limit, 0),
+                (0, frame_size_limit),
+                (frame_size_limit, frame_size_limit),
+            ] {
+                let (limited_have_ids, limited_need_ids, limited_rounds) =
+                    sync(client_limit, relay_limit);
+
+                // Every ID is reported exactly once
+                assert_eq!(limited_have_ids, have_ids);
+                assert_eq!(limited_need_ids, need_ids);
+                assert!(limited_rounds >= rounds);
+            }
+        }
+    }
+
+    #[test]
+    fn test_protocol_version_negotiation() {
+        let mut storage = NegentropyStorageVector::new();
+        storage.insert(0, Id::from_byte_array([0xaa; 32])).unwrap();
+        storage.seal().unwrap();
+
+        // Unsupported version: the relay replies with its highest one
+        let mut relay = Negentropy::borrowed(&storage, 0).unwrap();
+        assert_eq!(relay.reconcile(&[0x62]).unwrap(), vec![0x61]);
+        assert_eq!(relay.reconcile(&[0x70]), Err(Error::InvalidProtocolVersion));
+
+        // No lower version to retry with
+        let mut client = Negentropy::borrowed(&storage, 0).unwrap();
+        client.initiate().unwrap();
+        assert_eq!(
+            client.reconcile_with_ids(&[0x60], &mut Vec::new(), &mut Vec::new()),
+            Err(Error::UnsupportedProtocolVersion)
+        );
+
+        let mut client = Negentropy::borrowed(&storage, 0).unwrap();
+        assert_eq!(
+            client.set_supported_versions(SupportedVersions::empty()),
+            Err(Error::NoSupportedVersions)
+        );
+        let init_output = client.initiate().unwrap();
+        assert_eq!(client.protocol_version(), PROTOCOL_VERSION);
+        assert_eq!(
+            client.set_supported_versions(SupportedVersions::default()),
+            Err(Error::AlreadyBuiltInitialMessage)
+        );
+
+        let reconcile_output = relay.reconcile(&init_output).unwrap();
+        assert_eq!(relay.protocol_version(), PROTOCOL_VERSION);
+        assert_eq!(
+            client
+                .reconcile_with_ids(&reconcile_output, &mut Vec::new(), &mut Vec::new())
+                .unwrap(),
+            None
+        );
+    }
+
+    #[cfg(feature = "experimental-id-prefix")]
+    #[test]
+    fn test_protocol_version_retry() {
+        let mut storage = NegentropyStorageVector::new();
+        storage.insert(0, Id::from_byte_array([0xaa; 32])).unwrap();
+        storage.seal().unwrap();
+
+        let mut client = Negentropy::borrowed(&storage, 0).unwrap();
+        client
+            .set_supported_versions(SupportedVersions::implemented())
+            .unwrap();
+        let init_output = client.initiate().unwrap();
+        assert_eq!(init_output[0], ID_PREFIX_PROTOCOL_VERSION as u8);
+        assert_eq!(client.protocol_version(), ID_PREFIX_PROTOCOL_VERSION);
+
+        // The relay only supports version 1: the client retries with it
+        let mut relay = Negentropy::borrowed(&storage, 0).unwrap();
+        let reconcile_output = relay.reconcile(&init_output).unwrap();
+        assert_eq!(reconcile_output, vec![PROTOCOL_VERSION as u8]);
+
+        let retry = client
+            .reconcile_with_ids(&reconcile_output, &mut Vec::new(), &mut Vec::new())
+            .unwrap()
+            .unwrap();
+        assert_eq!(retry[0], PROTOCOL_VERSION as u8);
+        assert_eq!(client.protocol_version(), PROTOCOL_VERSION);
+
+        let reconcile_output = relay.reconcile(&retry).unwrap();
+        assert_eq!(
+            client
+                .reconcile_with_ids(&reconcile_output, &mut Vec::new(), &mut Vec::new())
+                .unwrap(),
+            None
+        );
+    }
+
+    #[cfg(feature = "experimental-id-prefix")]
+    fn sync_id_prefixes(
+        client_ids: &[(u64, Id)],
+        relay_ids: &[(u64, Id)],
+        client_prefix_len: Option<usize>,
+        relay_prefix_len: Option<usize>,
+        frame_size_limit: u64,
+    ) -> (Vec<Id>, Vec<Id>, usize) {
+        // Client
+        let mut storage_client = NegentropyStorageVector::new();
+        for (timestamp, id) in client_ids.iter() {
+            storage_client.insert(*timestamp, *id).unwrap();
+        }
+        storage_client.seal().unwrap();
+
+        let mut client = Negentropy::borrowed(&storage_client, frame_size_limit).unwrap();
+        if let Some(prefix_len) = client_prefix_len {
+            client.enable_id_prefixes(prefix_len).unwrap();
+        }
+        let mut msg = client.initiate().unwrap();
+
+        // Relay
+        let mut storage_relay = NegentropyStorageVector::new();
+        for (timestamp, id) in relay_ids.iter() {
+            storage_relay.insert(*timestamp, *id).unwrap();
+        }
+        storage_relay.seal().unwrap();
+
+        let mut relay = Negentropy::borrowed(&storage_relay, frame_size_limit).unwrap();
+        if let Some(prefix_len) = relay_prefix_len {
+            relay.enable_id_prefixes(prefix_len).unwrap();
+        }
+
+        let mut have_ids = Vec::new();
+        let mut need_ids = Vec::new();
+        let mut bytes: usize = msg.len();
+        loop {
+            let response = relay.reconcile(&msg).unwrap();
+            bytes += response.len();
+            match client
+                .reconcile_with_ids(&response, &mut have_ids, &mut need_ids)
+                .unwrap()
+            {
+                Some(next) => {
+                    bytes += next.len();
+                    msg = next;
+                }
+                None => break,
+            }
+        }
+
+        have_ids.sort();
+        need_ids.sort();
+        (have_ids, need_ids, bytes)
+    }
+
+    #[cfg(feature = "experimental-id-prefix")]
+    #[test]
+    fn test_reconciliation_id_prefixes_equivalence() {
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = || {
+            // xorshift64
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for round in 0..8 {
+            let mut client_ids = Vec::new();
+            let mut relay_ids = Vec::new();
+            for _ in 0..(500 + round * 250) {
+                let mut id = [0u8; 32];
+                for chunk in id.chunks_exact_mut(8) {
+                    chunk.copy_from_slice(&next().to_le_bytes());
+                }
+                let item = (next() % 10_000, Id::from_byte_array(id));
+                match next() % 4 {
+                    0 => client_ids.push(item),
+                    1 => relay_ids.push(item),
+                    _ => {
+                        client_ids.push(item);
+                        relay_ids.push(item);
+                    }
+                }
+            }
+
+            for frame_size_limit in [0, 4096] {
+                let (have_ids, need_ids, _) =
+                    sync_id_prefixes(&client_ids, &relay_ids, None, None, frame_size_limit);
+
+                for prefix_len in [1, 2, 4, 8] {
+                    let (prefix_have_ids, prefix_need_ids, _) = sync_id_prefixes(
+                        &client_ids,
+                        &relay_ids,
+                        Some(prefix_len),
+                        Some(prefix_len),
+                        frame_size_limit,
+                    );
+                    assert_eq!(prefix_have_ids, have_ids);
+                    assert_eq!(prefix_need_ids, need_ids);
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "experimental-id-prefix")]
+    #[test]
+    fn test_reconciliation_id_prefixes_fallback() {
+        let client_ids: Vec<(u64, Id)> = (0..100u8)
+            .map(|i| (i as u64, Id::from_byte_array([i; 32])))
+            .collect();
+        let relay_ids: Vec<(u64, Id)> = (50..200u8)
+            .map(|i| (i as u64, Id::from_byte_array([i; 32])))
+            .collect();
+
+        let (have_ids, need_ids, _) = sync_id_prefixes(&client_ids, &relay_ids, None, None, 0);
+        assert_eq!(have_ids.len(), 50);
+        assert_eq!(need_ids.len(), 100);
+
+        // Relay didn't opt in: the client restarts with the standard protocol
+        let (fallback_have_ids, fallback_need_ids, _) =
+            sync_id_prefixes(&client_ids, &relay_ids, Some(4), None, 0);
+        assert_eq!(fallback_have_ids, have_ids);
+        assert_eq!(fallback_need_ids, need_ids);
+
+        // Client didn't opt in
+        let (fallback_have_ids, fallback_need_ids, _) =
+            sync_id_prefixes(&client_ids, &relay_ids, None, Some(4), 0);
+        assert_eq!(fallback_have_ids, have_ids);
+        assert_eq!(fallback_need_ids, need_ids);
+
+        // Extension modes are rejected within the standard protocol
+        let mut storage = NegentropyStorageVector::new();
+        storage.seal().unwrap();
+        let mut relay = Negentropy::borrowed(&storage, 0).unwrap();
+        assert_eq!(
+            relay.reconcile(&[0x61, 0x00, 0x00, 0x03, 0x00, 0x04]),
+            Err(Error::UnexpectedMode(3))
+        );
+
+        assert_eq!(
+            relay.enable_id_prefixes(0),
+            Err(Error::InvalidIdPrefixLength)
+        );
+        assert_eq!(
+            relay.enable_id_prefixes(33),
+            Err(Error::InvalidIdPrefixLength)
+        );
+    }
+
+    #[cfg(feature = "experimental-id-prefix")]
+    #[test]
+    fn test_reconciliation_id_prefixes_malformed() {
+        let mut storage = NegentropyStorageVector::new();
+        storage.seal().unwrap();
+        let mut client = Negentropy::borrowed(&storage, 0).unwrap();
+        client.enable_id_prefixes(4).unwrap();
+        client.initiate().unwrap();
+
+        // Huge ID count in an ID prefix diff: must fail without reserving memory for it
+        let mut msg: Vec<u8> = vec![ID_PREFIX_PROTOCOL_VERSION as u8, 0x00, 0x00, 0x04];
+        msg.extend(encode_var_int(1 << 60));
+        let mut have_ids = Vec::new();
+        let mut need_ids = Vec::new();
+        assert_eq!(
+            client.reconcile_with_ids(&msg, &mut have_ids, &mut need_ids),
+            Err(Error::ParseEndsPrematurely)
+        );
+    }
+
+    #[cfg(feature = "experimental-id-prefix")]
+    #[test]
+    fn test_reconciliation_id_prefixes_bandwidth() {
+        let id = |i: u16| {
+            let mut id = [0u8; 32];
+            id[..2].copy_from_slice(&i.wrapping_mul(40503).to_be_bytes());
+            Id::from_byte_array(id)
+        };
+
+        let client_ids: Vec<(u64, Id)> = (0..3000u16).map(|i| (i as u64, id(i))).collect();
+        let relay_ids: Vec<(u64, Id)> = (0..3000u16)
+            .filter(|i| i % 10 != 0)
+            .map(|i| (i as u64, id(i)))
+            .collect();
+
+        let (have_ids, need_ids, bytes) = sync_id_prefixes(&client_ids, &relay_ids, None, None, 0);
+        let (prefix_have_ids, prefix_need_ids, prefix_bytes) =
+            sync_id_prefixes(&client_ids, &relay_ids, Some(4), Some(4), 0);
+
+        assert_eq!(have_ids.len(), 300);
+        assert_eq!(prefix_have_ids, have_ids);
+        assert_eq!(prefix_need_ids, need_ids);
+        assert!(prefix_bytes < bytes);
+    }
 }
 
 #[cfg(bench)]
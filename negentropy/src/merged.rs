@@ -0,0 +1,365 @@
+// Copyright (c) 2023 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! Union of several storages
+
+use alloc::collections::BinaryHeap;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+
+use crate::constants::ID_SIZE;
+use crate::{Accumulator, Bound, Error, Item, NegentropyStorageBase};
+
+/// Union of several sorted storages (shards), presented as one sorted and deduplicated set
+///
+/// Nothing is copied: positions are resolved with rank queries
+/// ([`NegentropyStorageBase::find_lower_bound`]) on every shard, and fingerprints are computed
+/// from the per-shard [sums](NegentropyStorageBase::accumulate).
+///
+/// Shards can be of different types with `MergedStorage<Box<dyn NegentropyStorageBase>>`
+/// and must not change while merged.
+#[derive(Debug)]
+pub struct MergedStorage<S, const N: usize = ID_SIZE> {
+    shards: Vec<S>,
+    sizes: Vec<usize>,
+    /// Extra copies of the items found in more than one shard, sorted
+    duplicates: Vec<Item<N>>,
+    size: usize,
+}
+
+impl<S, const N: usize> MergedStorage<S, N>
+where
+    S: NegentropyStorageBase<N>,
+{
+    /// Merge shards
+    ///
+    /// Scans all the shards once to find the items present in more than one of them.
+    pub fn new(shards: Vec<S>) -> Result<Self, Error> {
+        let mut merged: Self = Self::new_disjoint(shards)?;
+
+        let mut heap: BinaryHeap<Reverse<(Item<N>, usize)>> = BinaryHeap::new();
+        let mut positions: Vec<usize> = Vec::with_capacity(merged.shards.len());
+        for (index, shard) in merged.shards.iter().enumerate() {
+            if let Some(item) = shard.get_item(0)? {
+                heap.push(Reverse((item, index)));
+            }
+            positions.push(0);
+        }
+
+        let mut last: Option<Item<N>> = None;
+        while let Some(Reverse((item, index))) = heap.pop() {
+            if last == Some(item) {
+                merged.duplicates.push(item);
+            }
+            last = Some(item);
+
+            positions[index] += 1;
+            if let Some(next) = merged.shards[index].get_item(positions[index])? {
+                heap.push(Reverse((next, index)));
+            }
+        }
+
+        merged.size -= merged.duplicates.len();
+
+        Ok(merged)
+    }
+
+    /// Merge shards that have no item in common, without scanning them
+    ///
+    /// Items found in more than one shard break reconciliation.
+    pub fn new_disjoint(shards: Vec<S>) -> Result<Self, Error> {
+        let sizes: Vec<usize> = shards
+            .iter()
+            .map(|shard| shard.size())
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            size: sizes.iter().sum(),
+            shards,
+            sizes,
+            duplicates: Vec::new(),
+        })
+    }
+
+    /// Get the shards
+    #[inline]
+    pub fn shards(&self) -> &[S] {
+        &self.shards
+    }
+
+    /// Get the shards back
+    #[inline]
+    pub fn into_shards(self) -> Vec<S> {
+        self.shards
+    }
+
+    /// Position of `value` in the merged set
+    fn rank(&self, value: &Bound<N>) -> usize {
+        let mut rank: usize = 0;
+
+        for (shard, size) in self.shards.iter().zip(self.sizes.iter()) {
+            rank += shard.find_lower_bound(0, *size, value);
+        }
+
+        rank - self.duplicates.partition_point(|item| *item < value.item)
+    }
+
+    /// Per-shard positions of `value`
+    fn lower_bounds(&self, value: Option<&Bound<N>>) -> Vec<usize> {
+        self.shards
+            .iter()
+            .zip(self.sizes.iter())
+            .map(|(shard, size)| match value {
+                Some(value) => shard.find_lower_bound(0, *size, value),
+                None => *size,
+            })
+            .collect()
+    }
+
+    /// Find the item at position `i` of the merged set
+    fn select(&self, i: usize) -> Result<Option<Item<N>>, Error> {
+        if i >= self.size {
+            return Ok(None);
+        }
+
+        for (shard, size) in self.shards.iter().zip(self.sizes.iter()) {
+            // Last item of the shard with a merged position not greater than `i`
+            let (mut first, mut count) = (0, *size);
+            while count > 0 {
+                let step: usize = count / 2;
+                let it: usize = first + step;
+                let item: Item<N> = shard.get_item(it)?.ok_or(Error::BadRange)?;
+
+                if self.rank(&Bound::from_item(&item)) <= i {
+                    first = it + 1;
+                    count -= step + 1;
+                } else {
+                    count = step;
+                }
+            }
+
+            if first > 0 {
+                let item: Item<N> = shard.get_item(first - 1)?.ok_or(Error::BadRange)?;
+                if self.rank(&Bound::from_item(&item)) == i {
+                    return Ok(Some(item));
+                }
+            }
+        }
+
+        Err(Error::BadRange)
+    }
+
+    fn check_bounds(&self, begin: usize, end: usize) -> Result<(), Error> {
+        if begin > end || end > self.size {
+            return Err(Error::BadRange);
+        }
+        Ok(())
+    }
+}
+
+impl<S, const N: usize> NegentropyStorageBase<N> for MergedStorage<S, N>
+where
+    S: NegentropyStorageBase<N>,
+{
+    fn size(&self) -> Result<usize, Error> {
+        Ok(self.size)
+    }
+
+    fn get_item(&self, i: usize) -> Result<Option<Item<N>>, Error> {
+        self.select(i)
+    }
+
+    fn iterate(
+        &self,
+        begin: usize,
+        end: usize,
+        cb: &mut dyn FnMut(Item<N>, usize) -> Result<bool, Error>,
+    ) -> Result<(), Error> {
+        self.check_bounds(begin, end)?;
+
+        let first: Item<N> = match self.select(begin)? {
+            Some(first) => first,
+            None => return Ok(()),
+        };
+
+        let mut positions: Vec<usize> = self.lower_bounds(Some(&Bound::from_item(&first)));
+        let mut heap: BinaryHeap<Reverse<(Item<N>, usize)>> = BinaryHeap::new();
+        for (index, shard) in self.shards.iter().enumerate() {
+            if let Some(item) = shard.get_item(positions[index])? {
+                heap.push(Reverse((item, index)));
+            }
+        }
+
+        for i in begin..end {
+            let item: Item<N> = match heap.peek() {
+                Some(Reverse((item, _))) => *item,
+                None => return Err(Error::BadRange),
+            };
+
+            // Advance every shard holding the item
+            while let Some(Reverse((next, index))) = heap.peek().copied() {
+                if next != item {
+                    break;
+                }
+
+                heap.pop();
+                positions[index] += 1;
+                if let Some(next) = self.shards[index].get_item(positions[index])? {
+                    heap.push(Reverse((next, index)));
+                }
+            }
+
+            if !cb(item, i)? {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn find_lower_bound(&self, first: usize, last: usize, value: &Bound<N>) -> usize {
+        self.rank(value).max(first).min(last)
+    }
+
+    fn accumulate(&self, begin: usize, end: usize) -> Result<Accumulator<N>, Error> {
+        self.check_bounds(begin, end)?;
+
+        let mut out: Accumulator<N> = Accumulator::new();
+
+        if begin == end {
+            return Ok(out);
+        }
+
+        let lower: Option<Item<N>> = self.select(begin)?;
+        let upper: Option<Item<N>> = self.select(end)?;
+
+        let begins: Vec<usize> = self.lower_bounds(lower.as_ref().map(Bound::from_item).as_ref());
+        let ends: Vec<usize> = self.lower_bounds(upper.as_ref().map(Bound::from_item).as_ref());
+
+        for (shard, (b, e)) in self.shards.iter().zip(begins.into_iter().zip(ends)) {
+            out.add(&shard.accumulate(b, e)?.to_bytes());
+        }
+
+        // Added once per shard holding them
+        let first: usize = match lower {
+            Some(lower) => self.duplicates.partition_point(|item| *item < lower),
+            None => self.duplicates.len(),
+        };
+        let last: usize = match upper {
+            Some(upper) => self.duplicates.partition_point(|item| *item < upper),
+            None => self.duplicates.len(),
+        };
+        for item in self.duplicates[first..last].iter() {
+            out.sub(item.id.as_bytes());
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    use alloc::vec;
+
+    use super::*;
+    use crate::{Id, Negentropy, NegentropyStorageBytes, NegentropyStorageVector, Sha256};
+
+    fn item(i: u64) -> Item {
+        let mut id = [0u8; 32];
+        id[..8].copy_from_slice(&i.wrapping_mul(0x9E37_79B9_7F4A_7C15).to_be_bytes());
+        Item::with_timestamp_and_id(i / 4, Id::from_byte_array(id))
+    }
+
+    fn storage<I>(iter: I) -> NegentropyStorageVector
+    where
+        I: IntoIterator<Item = u64>,
+    {
+        iter.into_iter().map(item).collect()
+    }
+
+    #[test]
+    fn test_merged_storage() {
+        // Overlapping shards
+        let shards = vec![
+            storage((0..300).step_by(2)),
+            storage((0..300).step_by(3)),
+            storage(250..400),
+            storage(core::iter::empty()),
+        ];
+        let expected = storage((0..400).filter(|i| *i >= 250 || i % 2 == 0 || i % 3 == 0));
+
+        let merged = MergedStorage::new(shards).unwrap();
+        let size = expected.size().unwrap();
+        assert_eq!(merged.size().unwrap(), size);
+
+        for i in 0..=size {
+            assert_eq!(merged.get_item(i).unwrap(), expected.get_item(i).unwrap());
+        }
+
+        let mut items = Vec::new();
+        merged
+            .iterate(10, size, &mut |item, i| {
+                assert_eq!(expected.get_item(i).unwrap(), Some(item));
+                items.push(item);
+                Ok(items.len() < 100)
+            })
+            .unwrap();
+        assert_eq!(items.len(), 100);
+
+        for (begin, end) in [(0, 0), (0, size), (13, 150), (100, size), (size, size)] {
+            assert_eq!(
+                merged.fingerprint(&Sha256, begin, end).unwrap().to_bytes(),
+                expected
+                    .fingerprint(&Sha256, begin, end)
+                    .unwrap()
+                    .to_bytes()
+            );
+        }
+
+        for i in (0..410).step_by(7) {
+            let bound = Bound::from_item(&item(i));
+            assert_eq!(
+                merged.find_lower_bound(0, size, &bound),
+                expected.find_lower_bound(0, size, &bound)
+            );
+            assert_eq!(
+                merged.find_lower_bound(5, 20, &bound),
+                expected.find_lower_bound(5, 20, &bound)
+            );
+        }
+
+        assert_eq!(
+            merged.iterate(0, size + 1, &mut |_, _| Ok(true)),
+            Err(Error::BadRange)
+        );
+    }
+
+    #[test]
+    fn test_merged_storage_reconciliation() {
+        // Hot in-memory tier and cold archive snapshot
+        let hot = storage(800..1000);
+        let cold = NegentropyStorageBytes::new(storage(0..850).to_bytes(true).unwrap()).unwrap();
+        let shards: Vec<Box<dyn NegentropyStorageBase>> = vec![Box::new(hot), Box::new(cold)];
+
+        let mut relay = Negentropy::owned(MergedStorage::new(shards).unwrap(), 0).unwrap();
+        let mut client = Negentropy::owned(storage(500..1200), 0).unwrap();
+
+        let mut have_ids = Vec::new();
+        let mut need_ids = Vec::new();
+        let mut msg = client.initiate().unwrap();
+        loop {
+            let response = relay.reconcile(&msg).unwrap();
+            match client
+                .reconcile_with_ids(&response, &mut have_ids, &mut need_ids)
+                .unwrap()
+            {
+                Some(next) => msg = next,
+                None => break,
+            }
+        }
+
+        assert_eq!(have_ids.len(), 200);
+        assert_eq!(need_ids.len(), 500);
+    }
+}
@@ -0,0 +1,349 @@
+// Copyright (c) 2023 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! Typed client and server sessions
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use crate::constants::ID_SIZE;
+use crate::hasher::{FingerprintHasher, Sha256};
+use crate::storage::{NegentropyStorageBase, Storage};
+use crate::{Error, Id, Negentropy, SupportedVersions};
+
+/// Negentropy client (initiator)
+#[derive(Debug)]
+pub struct NegentropyClient<'a, T, H = Sha256, const N: usize = ID_SIZE> {
+    inner: Negentropy<'a, T, H, N>,
+}
+
+impl<'a, T, const N: usize> NegentropyClient<'a, T, Sha256, N>
+where
+    T: NegentropyStorageBase<N>,
+{
+    /// Create new [`NegentropyClient`] instance
+    ///
+    /// Frame size limit must be `equal to 0` or `greater than 4096`
+    pub fn new(storage: Storage<'a, T>, frame_size_limit: u64) -> Result<Self, Error> {
+        Self::with_hasher(storage, Sha256, frame_size_limit)
+    }
+
+    /// Create new [`NegentropyClient`] instance from owned storage
+    ///
+    /// Frame size limit must be `equal to 0` or `greater than 4096`
+    pub fn owned(storage: T, frame_size_limit: u64) -> Result<Self, Error> {
+        Self::new(Storage::Owned(storage), frame_size_limit)
+    }
+
+    /// Create new [`NegentropyClient`] instance from borrowed storage
+    ///
+    /// Frame size limit must be `equal to 0` or `greater than 4096`
+    pub fn borrowed(storage: &'a T, frame_size_limit: u64) -> Result<Self, Error> {
+        Self::new(Storage::Borrowed(storage), frame_size_limit)
+    }
+
+    /// Create new [`NegentropyClient`] instance from shared storage
+    ///
+    /// Frame size limit must be `equal to 0` or `greater than 4096`
+    pub fn shared(storage: Arc<T>, frame_size_limit: u64) -> Result<Self, Error> {
+        Self::new(Storage::Shared(storage), frame_size_limit)
+    }
+}
+
+impl<'a, T, H, const N: usize> NegentropyClient<'a, T, H, N>
+where
+    T: NegentropyStorageBase<N>,
+    H: FingerprintHasher,
+{
+    /// Create new [`NegentropyClient`] instance with a custom fingerprint hasher
+    ///
+    /// Frame size limit must be `equal to 0` or `greater than 4096`
+    pub fn with_hasher(
+        storage: Storage<'a, T>,
+        hasher: H,
+        frame_size_limit: u64,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            inner: Negentropy::with_hasher(storage, hasher, frame_size_limit)?,
+        })
+    }
+
+    /// Build the initial message
+    pub fn initiate(&mut self) -> Result<Vec<u8>, Error> {
+        self.inner.initiate()
+    }
+
+    /// Reconcile a server message
+    ///
+    /// Returns the next message to send, or `None` when reconciliation is complete.
+    ///
+    /// Can be called without [`NegentropyClient::initiate`] to resume a flow started by another instance.
+    pub fn reconcile(
+        &mut self,
+        query: &[u8],
+        have_ids: &mut Vec<Id<N>>,
+        need_ids: &mut Vec<Id<N>>,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        if !self.inner.is_initiator() {
+            self.inner.set_initiator();
+        }
+
+        self.inner.reconcile_with_ids(query, have_ids, need_ids)
+    }
+
+    /// Get the supported-version mask
+    pub fn supported_versions(&self) -> SupportedVersions {
+        self.inner.supported_versions()
+    }
+
+    /// Set the supported-version mask
+    ///
+    /// Must be called before [`NegentropyClient::initiate`].
+    pub fn set_supported_versions(&mut self, versions: SupportedVersions) -> Result<(), Error> {
+        self.inner.set_supported_versions(versions)
+    }
+
+    /// Get the protocol version in use
+    pub fn protocol_version(&self) -> u64 {
+        self.inner.protocol_version()
+    }
+
+    /// Enable the experimental ID prefix extension: see [`Negentropy::enable_id_prefixes`]
+    #[cfg(feature = "experimental-id-prefix")]
+    pub fn enable_id_prefixes(&mut self, prefix_len: usize) -> Result<(), Error> {
+        self.inner.enable_id_prefixes(prefix_len)
+    }
+}
+
+/// Negentropy server (non-initiator)
+#[derive(Debug)]
+pub struct NegentropyServer<'a, T, H = Sha256, const N: usize = ID_SIZE> {
+    inner: Negentropy<'a, T, H, N>,
+}
+
+impl<'a, T, const N: usize> NegentropyServer<'a, T, Sha256, N>
+where
+    T: NegentropyStorageBase<N>,
+{
+    /// Create new [`NegentropyServer`] instance
+    ///
+    /// Frame size limit must be `equal to 0` or `greater than 4096`
+    pub fn new(storage: Storage<'a, T>, frame_size_limit: u64) -> Result<Self, Error> {
+        Self::with_hasher(storage, Sha256, frame_size_limit)
+    }
+
+    /// Create new [`NegentropyServer`] instance from owned storage
+    ///
+    /// Frame size limit must be `equal to 0` or `greater than 4096`
+    pub fn owned(storage: T, frame_size_limit: u64) -> Result<Self, Error> {
+        Self::new(Storage::Owned(storage), frame_size_limit)
+    }
+
+    /// Create new [`NegentropyServer`] instance from borrowed storage
+    ///
+    /// Frame size limit must be `equal to 0` or `greater than 4096`
+    pub fn borrowed(storage: &'a T, frame_size_limit: u64) -> Result<Self, Error> {
+        Self::new(Storage::Borrowed(storage), frame_size_limit)
+    }
+
+    /// Create new [`NegentropyServer`] instance from shared storage
+    ///
+    /// Frame size limit must be `equal to 0` or `greater than 4096`
+    pub fn shared(storage: Arc<T>, frame_size_limit: u64) -> Result<Self, Error> {
+        Self::new(Storage::Shared(storage), frame_size_limit)
+    }
+}
+
+impl<'a, T, H, const N: usize> NegentropyServer<'a, T, H, N>
+where
+    T: NegentropyStorageBase<N>,
+    H: FingerprintHasher,
+{
+    /// Create new [`NegentropyServer`] instance with a custom fingerprint hasher
+    ///
+    /// Frame size limit must be `equal to 0` or `greater than 4096`
+    pub fn with_hasher(
+        storage: Storage<'a, T>,
+        hasher: H,
+        frame_size_limit: u64,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            inner: Negentropy::with_hasher(storage, hasher, frame_size_limit)?,
+        })
+    }
+
+    /// Reconcile a client message
+    pub fn reconcile(&mut self, query: &[u8]) -> Result<Vec<u8>, Error> {
+        self.inner.reconcile(query)
+    }
+
+    /// Reconcile a client message, reporting what was learned about the client's set:
+    /// see [`Negentropy::reconcile_with_diff`]
+    pub fn reconcile_with_diff(
+        &mut self,
+        query: &[u8],
+        have_ids: &mut Vec<Id<N>>,
+        need_ids: &mut Vec<Id<N>>,
+    ) -> Result<Vec<u8>, Error> {
+        self.inner.reconcile_with_diff(query, have_ids, need_ids)
+    }
+
+    /// Get the supported-version mask
+    pub fn supported_versions(&self) -> SupportedVersions {
+        self.inner.supported_versions()
+    }
+
+    /// Set the supported-version mask
+    pub fn set_supported_versions(&mut self, versions: SupportedVersions) -> Result<(), Error> {
+        self.inner.set_supported_versions(versions)
+    }
+
+    /// Get the protocol version of the last reconciled message
+    pub fn protocol_version(&self) -> u64 {
+        self.inner.protocol_version()
+    }
+
+    /// Enable the experimental ID prefix extension: see [`Negentropy::enable_id_prefixes`]
+    #[cfg(feature = "experimental-id-prefix")]
+    pub fn enable_id_prefixes(&mut self, prefix_len: usize) -> Result<(), Error> {
+        self.inner.enable_id_prefixes(prefix_len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NegentropyStorageVector;
+
+    #[test]
+    fn test_client_server_reconciliation() {
+        // Client
+        let mut storage_client = NegentropyStorageVector::new();
+        for i in 0..100u8 {
+            storage_client
+                .insert(i as u64, Id::from_byte_array([i; 32]))
+                .unwrap();
+        }
+        storage_client.seal().unwrap();
+
+        let mut client = NegentropyClient::borrowed(&storage_client, 0).unwrap();
+        let mut msg = client.initiate().unwrap();
+        assert_eq!(client.initiate(), Err(Error::AlreadyBuiltInitialMessage));
+
+        // Relay
+        let mut storage_relay = NegentropyStorageVector::new();
+        for i in 50..200u8 {
+            storage_relay
+                .insert(i as u64, Id::from_byte_array([i; 32]))
+                .unwrap();
+        }
+        storage_relay.seal().unwrap();
+
+        let mut relay = NegentropyServer::owned(storage_relay, 0).unwrap();
+
+        let mut have_ids = Vec::new();
+        let mut need_ids = Vec::new();
+        loop {
+            let response = relay.reconcile(&msg).unwrap();
+            match client
+                .reconcile(&response, &mut have_ids, &mut need_ids)
+                .unwrap()
+            {
+                Some(next) => msg = next,
+                None => break,
+            }
+        }
+
+        have_ids.sort();
+        need_ids.sort();
+        assert_eq!(
+            have_ids,
+            (0..50u8)
+                .map(|i| Id::from_byte_array([i; 32]))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            need_ids,
+            (100..200u8)
+                .map(|i| Id::from_byte_array([i; 32]))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_client_resume() {
+        let mut storage = NegentropyStorageVector::new();
+        storage.insert(0, Id::from_byte_array([0xaa; 32])).unwrap();
+        storage.seal().unwrap();
+
+        let mut client = NegentropyClient::borrowed(&storage, 0).unwrap();
+        let msg = client.initiate().unwrap();
+
+        let mut relay = NegentropyServer::borrowed(&storage, 0).unwrap();
+        let response = relay.reconcile(&msg).unwrap();
+
+        // Resume with a new instance
+        let mut client = NegentropyClient::borrowed(&storage, 0).unwrap();
+        assert_eq!(
+            client
+                .reconcile(&response, &mut Vec::new(), &mut Vec::new())
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_shared_storage_sessions() {
+        let mut storage_relay = NegentropyStorageVector::new();
+        for i in 0..200u8 {
+            storage_relay
+                .insert(i as u64, Id::from_byte_array([i; 32]))
+                .unwrap();
+        }
+        storage_relay.seal().unwrap();
+        let storage_relay = Arc::new(storage_relay);
+
+        let handles: Vec<_> = (0..4u8)
+            .map(|n| {
+                let storage_relay = Arc::clone(&storage_relay);
+                std::thread::spawn(move || {
+                    let mut storage_client = NegentropyStorageVector::new();
+                    for i in (n * 50)..(n * 50 + 10) {
+                        storage_client
+                            .insert(i as u64, Id::from_byte_array([i; 32]))
+                            .unwrap();
+                    }
+                    storage_client.seal().unwrap();
+
+                    let mut client = NegentropyClient::owned(storage_client, 0).unwrap();
+                    let mut relay = NegentropyServer::shared(storage_relay, 0).unwrap();
+
+                    let mut have_ids = Vec::new();
+                    let mut need_ids = Vec::new();
+                    let mut msg = client.initiate().unwrap();
+                    loop {
+                        let response = relay.reconcile(&msg).unwrap();
+                        match client
+                            .reconcile(&response, &mut have_ids, &mut need_ids)
+                            .unwrap()
+                        {
+                            Some(next) => msg = next,
+                            None => break,
+                        }
+                    }
+
+                    assert!(have_ids.is_empty());
+                    assert_eq!(need_ids.len(), 190);
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // All sessions dropped their reference
+        assert_eq!(Arc::strong_count(&storage_relay), 1);
+    }
+}
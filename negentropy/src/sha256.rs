@@ -1,8 +1,6 @@
 // Copyright (c) 2023 Yuki Kishimoto
 // Distributed under the MIT software license
 
-use alloc::vec::Vec;
-
 const K: [u32; 64] = [
     0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
     0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
@@ -17,79 +15,28 @@ const SHA256_INIT: [u32; 8] = [
     0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
 ];
 
-pub(crate) fn hash(mut data: Vec<u8>) -> [u8; 32] {
-    let original_len: usize = data.len();
-    let bit_len: u64 = (original_len * 8) as u64;
-
-    // Pre-processing: padding the message
-    data.push(0x80); // Append a '1' bit
-    while data.len() % 64 != 56 {
-        data.push(0x00);
-    }
-
-    // Append the original bit length as a 64-bit big-endian integer
-    let mut bit_len_bytes: [u8; 8] = [0; 8];
-    for i in 0..8 {
-        bit_len_bytes[7 - i] = ((bit_len >> (i * 8)) & 0xff) as u8;
-    }
-    data.extend_from_slice(&bit_len_bytes);
+pub(crate) fn hash(data: &[u8]) -> [u8; 32] {
+    let bit_len: u64 = (data.len() as u64) * 8;
 
     let mut hash: [u32; 8] = SHA256_INIT;
 
-    for chunk in data.chunks(64) {
-        let mut w: [u32; 64] = [0u32; 64];
-        for (i, chunk_byte) in chunk.iter().enumerate() {
-            w[i / 4] |= u32::from(*chunk_byte) << (24 - (i % 4) * 8);
-        }
+    let mut chunks = data.chunks_exact(64);
+    for chunk in &mut chunks {
+        compress(&mut hash, chunk);
+    }
 
-        for i in 16..64 {
-            let s0: u32 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
-            let s1: u32 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
-            w[i] = w[i - 16]
-                .wrapping_add(s0)
-                .wrapping_add(w[i - 7])
-                .wrapping_add(s1);
-        }
+    // Pre-processing: padding the message (the tail is copied in a fixed buffer)
+    let tail: &[u8] = chunks.remainder();
+    let mut block: [u8; 128] = [0u8; 128];
+    block[..tail.len()].copy_from_slice(tail);
+    block[tail.len()] = 0x80; // Append a '1' bit
 
-        let mut a: u32 = hash[0];
-        let mut b: u32 = hash[1];
-        let mut c: u32 = hash[2];
-        let mut d: u32 = hash[3];
-        let mut e: u32 = hash[4];
-        let mut f: u32 = hash[5];
-        let mut g: u32 = hash[6];
-        let mut h: u32 = hash[7];
-
-        for i in 0..64 {
-            let s1: u32 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
-            let ch: u32 = (e & f) ^ ((!e) & g);
-            let temp1: u32 = h
-                .wrapping_add(s1)
-                .wrapping_add(ch)
-                .wrapping_add(K[i])
-                .wrapping_add(w[i]);
-            let s0: u32 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
-            let maj: u32 = (a & b) ^ (a & c) ^ (b & c);
-            let temp2: u32 = s0.wrapping_add(maj);
-
-            h = g;
-            g = f;
-            f = e;
-            e = d.wrapping_add(temp1);
-            d = c;
-            c = b;
-            b = a;
-            a = temp1.wrapping_add(temp2);
-        }
+    // Append the original bit length as a 64-bit big-endian integer
+    let padded_len: usize = if tail.len() < 56 { 64 } else { 128 };
+    block[padded_len - 8..padded_len].copy_from_slice(&bit_len.to_be_bytes());
 
-        hash[0] = hash[0].wrapping_add(a);
-        hash[1] = hash[1].wrapping_add(b);
-        hash[2] = hash[2].wrapping_add(c);
-        hash[3] = hash[3].wrapping_add(d);
-        hash[4] = hash[4].wrapping_add(e);
-        hash[5] = hash[5].wrapping_add(f);
-        hash[6] = hash[6].wrapping_add(g);
-        hash[7] = hash[7].wrapping_add(h);
+    for chunk in block[..padded_len].chunks_exact(64) {
+        compress(&mut hash, chunk);
     }
 
     let mut result: [u8; 32] = [0u8; 32];
@@ -103,11 +50,67 @@ pub(crate) fn hash(mut data: Vec<u8>) -> [u8; 32] {
     result
 }
 
+fn compress(hash: &mut [u32; 8], chunk: &[u8]) {
+    let mut w: [u32; 64] = [0u32; 64];
+    for (i, chunk_byte) in chunk.iter().enumerate() {
+        w[i / 4] |= u32::from(*chunk_byte) << (24 - (i % 4) * 8);
+    }
+
+    for i in 16..64 {
+        let s0: u32 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1: u32 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16]
+            .wrapping_add(s0)
+            .wrapping_add(w[i - 7])
+            .wrapping_add(s1);
+    }
+
+    let mut a: u32 = hash[0];
+    let mut b: u32 = hash[1];
+    let mut c: u32 = hash[2];
+    let mut d: u32 = hash[3];
+    let mut e: u32 = hash[4];
+    let mut f: u32 = hash[5];
+    let mut g: u32 = hash[6];
+    let mut h: u32 = hash[7];
+
+    for i in 0..64 {
+        let s1: u32 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch: u32 = (e & f) ^ ((!e) & g);
+        let temp1: u32 = h
+            .wrapping_add(s1)
+            .wrapping_add(ch)
+            .wrapping_add(K[i])
+            .wrapping_add(w[i]);
+        let s0: u32 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj: u32 = (a & b) ^ (a & c) ^ (b & c);
+        let temp2: u32 = s0.wrapping_add(maj);
+
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(temp1);
+        d = c;
+        c = b;
+        b = a;
+        a = temp1.wrapping_add(temp2);
+    }
+
+    hash[0] = hash[0].wrapping_add(a);
+    hash[1] = hash[1].wrapping_add(b);
+    hash[2] = hash[2].wrapping_add(c);
+    hash[3] = hash[3].wrapping_add(d);
+    hash[4] = hash[4].wrapping_add(e);
+    hash[5] = hash[5].wrapping_add(f);
+    hash[6] = hash[6].wrapping_add(g);
+    hash[7] = hash[7].wrapping_add(h);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    const HASHES: [(&str, [u8; 32]); 10] = [
+    const HASHES: [(&str, [u8; 32]); 12] = [
         ("Bitcoin: A Peer-to-Peer Electronic Cash System", [0xef, 0xb5, 0xc6, 0x72, 0x9d, 0x8c, 0xe3, 0xe0,
             0x3f, 0xd0, 0x3a, 0xec, 0x34, 0x05, 0x40, 0xb2,
             0x4a, 0x78, 0x84, 0x54, 0xd4, 0x5e, 0x71, 0x70,
@@ -158,12 +161,23 @@ mod tests {
             0x0b, 0x24, 0x9b, 0x11, 0xe8, 0xf0, 0x7a, 0x51, 0xaf, 0xac, 0x45, 0x03, 0x7a, 0xfe, 0xe9, 0xd1
         ]
         ),
+        // Padding boundaries: one block, and two blocks
+        ("aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa", [
+            0x9f, 0x43, 0x90, 0xf8, 0xd3, 0x0c, 0x2d, 0xd9, 0x2e, 0xc9, 0xf0, 0x95, 0xb6, 0x5e, 0x2b, 0x9a,
+            0xe9, 0xb0, 0xa9, 0x25, 0xa5, 0x25, 0x8e, 0x24, 0x1c, 0x9f, 0x1e, 0x91, 0x0f, 0x73, 0x43, 0x18
+        ]
+        ),
+        ("aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa", [
+            0xff, 0xe0, 0x54, 0xfe, 0x7a, 0xe0, 0xcb, 0x6d, 0xc6, 0x5c, 0x3a, 0xf9, 0xb6, 0x1d, 0x52, 0x09,
+            0xf4, 0x39, 0x85, 0x1d, 0xb4, 0x3d, 0x0b, 0xa5, 0x99, 0x73, 0x37, 0xdf, 0x15, 0x46, 0x68, 0xeb
+        ]
+        ),
     ];
 
     #[test]
     fn test_sha256() {
         for (data, expected) in HASHES.iter() {
-            let hash = hash(data.as_bytes().to_vec());
+            let hash = hash(data.as_bytes());
             assert_eq!(&hash, expected);
         }
     }
@@ -177,7 +191,7 @@ mod benches {
     #[bench]
     pub fn sha256_hash(bh: &mut Bencher) {
         bh.iter(|| {
-            black_box(hash("aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"));
+            black_box(hash(b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"));
         });
     }
 }
@@ -0,0 +1,207 @@
+// Copyright (c) 2023 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! Copy-on-write storage snapshots
+
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::storage::NegentropyStorageVector;
+use crate::Error;
+
+/// Copy-on-write versioned storage
+///
+/// Sessions read a consistent [snapshot](VersionedStorage::snapshot) of the storage
+/// (see [`Negentropy::shared`](crate::Negentropy::shared)) while writers publish new versions
+/// with [`VersionedStorage::update`]. A version is dropped when its last snapshot is dropped.
+#[derive(Debug)]
+pub struct VersionedStorage<T = NegentropyStorageVector> {
+    current: RwLock<Arc<T>>,
+    writer: Mutex<()>,
+}
+
+impl<T> VersionedStorage<T>
+where
+    T: Clone,
+{
+    /// Create new versioned storage
+    #[inline]
+    pub fn new(storage: T) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(storage)),
+            writer: Mutex::new(()),
+        }
+    }
+
+    /// Get a snapshot of the current version
+    pub fn snapshot(&self) -> Arc<T> {
+        let current = self.current.read().unwrap_or_else(|e| e.into_inner());
+        Arc::clone(&current)
+    }
+
+    /// Update the storage
+    ///
+    /// `f` is applied to a copy of the current version, published only if `f` succeeds.
+    /// Snapshots taken before the update are not affected. Updates are serialized,
+    /// but don't block [`VersionedStorage::snapshot`] while copying the storage or applying `f`.
+    ///
+    /// Every update copies the whole storage: writers should batch their changes
+    /// (see [`NegentropyStorageVector::insert_batch`]) instead of updating once per item.
+    pub fn update<F, R>(&self, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut T) -> Result<R, Error>,
+    {
+        let _writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        let mut next: T = T::clone(&self.snapshot());
+        let res: R = f(&mut next)?;
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(next);
+        Ok(res)
+    }
+}
+
+impl<T> From<T> for VersionedStorage<T>
+where
+    T: Clone,
+{
+    fn from(storage: T) -> Self {
+        Self::new(storage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::sync::Weak;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{Id, Negentropy, NegentropyStorageBase};
+
+    fn sealed_storage(range: core::ops::Range<u64>) -> NegentropyStorageVector {
+        let mut storage = NegentropyStorageVector::new();
+        for i in range {
+            storage.insert(i, id(i)).unwrap();
+        }
+        storage.seal().unwrap();
+        storage
+    }
+
+    fn id(i: u64) -> Id {
+        let mut id = [0u8; 32];
+        id[..8].copy_from_slice(&i.to_be_bytes());
+        Id::from_byte_array(id)
+    }
+
+    fn insert(storage: &mut NegentropyStorageVector, i: u64) -> Result<(), Error> {
+        storage.unseal()?;
+        storage.insert(i, id(i))?;
+        storage.seal()
+    }
+
+    #[test]
+    fn test_versioned_storage_update() {
+        let versioned = VersionedStorage::new(sealed_storage(0..10));
+
+        let snapshot = versioned.snapshot();
+        versioned.update(|storage| insert(storage, 10)).unwrap();
+        assert_eq!(snapshot.size().unwrap(), 10);
+        assert_eq!(versioned.snapshot().size().unwrap(), 11);
+
+        // Failed updates are not published
+        assert_eq!(
+            versioned.update(|storage| storage.insert(11, id(11))),
+            Err(Error::AlreadySealed)
+        );
+        assert_eq!(versioned.snapshot().size().unwrap(), 11);
+
+        // Old version dropped with its last snapshot
+        let weak: Weak<NegentropyStorageVector> = Arc::downgrade(&snapshot);
+        drop(snapshot);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_versioned_storage_snapshot_during_update() {
+        let versioned = Arc::new(VersionedStorage::new(sealed_storage(0..10)));
+
+        versioned
+            .update(|storage| {
+                let (tx, rx) = mpsc::channel();
+                let reader = Arc::clone(&versioned);
+                thread::spawn(move || tx.send(reader.snapshot().size()));
+
+                // The update is still running: the reader gets the current version
+                let size = rx.recv_timeout(Duration::from_secs(10)).unwrap();
+                assert_eq!(size, Ok(10));
+
+                insert(storage, 10)
+            })
+            .unwrap();
+
+        assert_eq!(versioned.snapshot().size().unwrap(), 11);
+    }
+
+    #[test]
+    fn test_versioned_storage_concurrent_sessions_and_writers() {
+        let versioned = Arc::new(VersionedStorage::new(sealed_storage(0..100)));
+
+        let writers: Vec<_> = (0..2u64)
+            .map(|w| {
+                let versioned = Arc::clone(&versioned);
+                thread::spawn(move || {
+                    for i in 0..50 {
+                        let i: u64 = 100 + i * 2 + w;
+                        versioned.update(|storage| insert(storage, i)).unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        let sessions: Vec<_> = (0..4)
+            .map(|_| {
+                let versioned = Arc::clone(&versioned);
+                thread::spawn(move || {
+                    for _ in 0..10 {
+                        let snapshot = versioned.snapshot();
+                        let expected: usize = snapshot.size().unwrap();
+                        let weak: Weak<NegentropyStorageVector> = Arc::downgrade(&snapshot);
+
+                        let mut client = Negentropy::owned(sealed_storage(0..50), 0).unwrap();
+                        let mut relay = Negentropy::shared(snapshot, 0).unwrap();
+
+                        let mut have_ids = Vec::new();
+                        let mut need_ids = Vec::new();
+                        let mut msg = client.initiate().unwrap();
+                        loop {
+                            let response = relay.reconcile(&msg).unwrap();
+                            match client
+                                .reconcile_with_ids(&response, &mut have_ids, &mut need_ids)
+                                .unwrap()
+                            {
+                                Some(next) => msg = next,
+                                None => break,
+                            }
+                        }
+
+                        // Consistent with the snapshot the session started with
+                        assert!(have_ids.is_empty());
+                        assert_eq!(need_ids.len(), expected - 50);
+
+                        drop(relay);
+                        if let Some(snapshot) = weak.upgrade() {
+                            assert_eq!(snapshot.size().unwrap(), expected);
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for handle in writers.into_iter().chain(sessions) {
+            handle.join().unwrap();
+        }
+
+        let snapshot = versioned.snapshot();
+        assert_eq!(snapshot.size().unwrap(), 200);
+        assert_eq!(Arc::strong_count(&snapshot), 2);
+    }
+}
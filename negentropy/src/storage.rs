@@ -4,11 +4,16 @@
 
 //! Module that contains the various storage implementations
 
+use alloc::boxed::Box;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::iter::FromIterator;
+use core::mem;
 use core::ops::Deref;
 
 use crate::types::{Accumulator, Bound, Fingerprint, Item};
-use crate::{Error, Id};
+use crate::{Error, FingerprintHasher, Id, ID_SIZE};
 
 /// Storage
 #[derive(Debug)]
@@ -17,6 +22,8 @@ pub enum Storage<'a, T: 'a> {
     Borrowed(&'a T),
     /// Owned
     Owned(T),
+    /// Shared
+    Shared(Arc<T>),
 }
 
 impl<T> Deref for Storage<'_, T> {
@@ -26,51 +33,115 @@ impl<T> Deref for Storage<'_, T> {
         match self {
             Self::Borrowed(b) => b,
             Self::Owned(b) => b,
+            Self::Shared(b) => b,
         }
     }
 }
 
 /// NegentropyStorageBase
-pub trait NegentropyStorageBase {
+///
+/// `N` is the ID size (32 bytes by default).
+pub trait NegentropyStorageBase<const N: usize = ID_SIZE> {
     /// Size
     fn size(&self) -> Result<usize, Error>;
 
     /// Get Item
-    fn get_item(&self, i: usize) -> Result<Option<Item>, Error>;
+    fn get_item(&self, i: usize) -> Result<Option<Item<N>>, Error>;
 
     /// Iterate
     fn iterate(
         &self,
         begin: usize,
         end: usize,
-        cb: &mut dyn FnMut(Item, usize) -> Result<bool, Error>,
+        cb: &mut dyn FnMut(Item<N>, usize) -> Result<bool, Error>,
     ) -> Result<(), Error>;
 
     /// Find Lower Bound
-    fn find_lower_bound(&self, first: usize, last: usize, value: &Bound) -> usize;
+    fn find_lower_bound(&self, first: usize, last: usize, value: &Bound<N>) -> usize;
 
-    /// Fingerprint
-    fn fingerprint(&self, begin: usize, end: usize) -> Result<Fingerprint, Error> {
-        let mut out = Accumulator::new();
+    /// Sum of the IDs in the range
+    fn accumulate(&self, begin: usize, end: usize) -> Result<Accumulator<N>, Error> {
+        let mut out: Accumulator<N> = Accumulator::new();
 
-        self.iterate(begin, end, &mut |item: Item, _| {
-            out.add(&item.id)?;
+        self.iterate(begin, end, &mut |item: Item<N>, _| {
+            out.add(&item.id);
             Ok(true)
         })?;
 
-        out.get_fingerprint((end - begin) as u64)
+        Ok(out)
+    }
+
+    /// Fingerprint
+    fn fingerprint(
+        &self,
+        hasher: &dyn FingerprintHasher,
+        begin: usize,
+        end: usize,
+    ) -> Result<Fingerprint, Error> {
+        self.accumulate(begin, end)?
+            .get_fingerprint(hasher, (end - begin) as u64)
     }
 }
 
+macro_rules! impl_storage_base_for_pointer {
+    ($($ptr:ty),*) => {
+        $(
+            impl<T, const N: usize> NegentropyStorageBase<N> for $ptr
+            where
+                T: NegentropyStorageBase<N> + ?Sized,
+            {
+                fn size(&self) -> Result<usize, Error> {
+                    (**self).size()
+                }
+
+                fn get_item(&self, i: usize) -> Result<Option<Item<N>>, Error> {
+                    (**self).get_item(i)
+                }
+
+                fn iterate(
+                    &self,
+                    begin: usize,
+                    end: usize,
+                    cb: &mut dyn FnMut(Item<N>, usize) -> Result<bool, Error>,
+                ) -> Result<(), Error> {
+                    (**self).iterate(begin, end, cb)
+                }
+
+                fn find_lower_bound(&self, first: usize, last: usize, value: &Bound<N>) -> usize {
+                    (**self).find_lower_bound(first, last, value)
+                }
+
+                fn accumulate(&self, begin: usize, end: usize) -> Result<Accumulator<N>, Error> {
+                    (**self).accumulate(begin, end)
+                }
+
+                fn fingerprint(
+                    &self,
+                    hasher: &dyn FingerprintHasher,
+                    begin: usize,
+                    end: usize,
+                ) -> Result<Fingerprint, Error> {
+                    (**self).fingerprint(hasher, begin, end)
+                }
+            }
+        )*
+    };
+}
+
+impl_storage_base_for_pointer!(&T, Box<T>, Arc<T>);
+
 /// Negentropy Storage Vector
 #[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct NegentropyStorageVector {
-    items: Vec<Item>,
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct NegentropyStorageVector<const N: usize = ID_SIZE> {
+    items: Vec<Item<N>>,
     sealed: bool,
 }
 
 impl NegentropyStorageVector {
     /// Create new storage
+    ///
+    /// For ID sizes other than [`ID_SIZE`] use [`Default`] (i.e. `NegentropyStorageVector::<20>::default()`).
     #[inline]
     pub fn new() -> Self {
         Self::default()
@@ -84,19 +155,132 @@ impl NegentropyStorageVector {
             sealed: false,
         }
     }
+}
+
+impl<const N: usize> NegentropyStorageVector<N> {
+    /// Create new sealed storage from items sorted by `(timestamp, id)`, without duplicates
+    ///
+    /// Validates the order in `O(n)`: fails with [`Error::UnsortedItems`] otherwise.
+    pub fn from_sorted(items: Vec<Item<N>>) -> Result<Self, Error> {
+        if !is_strictly_sorted(&items) {
+            return Err(Error::UnsortedItems);
+        }
+
+        Ok(Self::from_sorted_unchecked(items))
+    }
+
+    /// Create new sealed storage from items sorted by `(timestamp, id)`, without duplicates
+    ///
+    /// The order is not validated, except in debug builds:
+    /// unsorted or duplicated items break reconciliation.
+    pub fn from_sorted_unchecked(items: Vec<Item<N>>) -> Self {
+        debug_assert!(is_strictly_sorted(&items), "items not sorted or not unique");
+
+        Self {
+            items,
+            sealed: true,
+        }
+    }
 
     /// Insert item
-    pub fn insert(&mut self, created_at: u64, id: Id) -> Result<(), Error> {
+    pub fn insert(&mut self, created_at: u64, id: Id<N>) -> Result<(), Error> {
         if self.sealed {
             return Err(Error::AlreadySealed);
         }
 
-        let elem: Item = Item::with_timestamp_and_id(created_at, id);
+        let elem: Item<N> = Item::with_timestamp_and_id(created_at, id);
         self.items.push(elem);
 
         Ok(())
     }
 
+    /// Insert a batch of items
+    ///
+    /// If the storage is sealed, only the new items are sorted and then merged into the existing ones
+    /// (`O(n + k log k)`), skipping duplicates: the storage stays sealed.
+    pub fn insert_batch<I>(&mut self, items: I)
+    where
+        I: IntoIterator<Item = Item<N>>,
+    {
+        if !self.sealed {
+            self.items.extend(items);
+            return;
+        }
+
+        let mut new_items: Vec<Item<N>> = items.into_iter().collect();
+
+        if new_items.is_empty() {
+            return;
+        }
+
+        new_items.sort();
+        new_items.dedup();
+
+        // Fast path: all new items come after the existing ones
+        if self.items.last().map_or(true, |last| *last < new_items[0]) {
+            self.items.extend(new_items);
+            return;
+        }
+
+        let old: Vec<Item<N>> = mem::take(&mut self.items);
+        let mut merged: Vec<Item<N>> = Vec::with_capacity(old.len() + new_items.len());
+        let (mut i, mut j) = (0, 0);
+
+        while i < old.len() && j < new_items.len() {
+            match old[i].cmp(&new_items[j]) {
+                Ordering::Less => {
+                    merged.push(old[i]);
+                    i += 1;
+                }
+                Ordering::Greater => {
+                    merged.push(new_items[j]);
+                    j += 1;
+                }
+                Ordering::Equal => {
+                    merged.push(old[i]);
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+
+        merged.extend_from_slice(&old[i..]);
+        merged.extend_from_slice(&new_items[j..]);
+
+        self.items = merged;
+    }
+
+    /// Remove item
+    ///
+    /// Returns `true` if the item was in the storage.
+    pub fn remove(&mut self, created_at: u64, id: Id<N>) -> bool {
+        let elem: Item<N> = Item::with_timestamp_and_id(created_at, id);
+
+        if self.sealed {
+            match self.items.binary_search(&elem) {
+                Ok(index) => {
+                    self.items.remove(index);
+                    true
+                }
+                Err(..) => false,
+            }
+        } else {
+            let len: usize = self.items.len();
+            self.items.retain(|item| *item != elem);
+            self.items.len() != len
+        }
+    }
+
+    /// Retain only the items for which `f` returns `true`
+    ///
+    /// Preserves the order: a sealed storage stays sealed.
+    pub fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&Item<N>) -> bool,
+    {
+        self.items.retain(f);
+    }
+
     /// Seal
     pub fn seal(&mut self) -> Result<(), Error> {
         if self.sealed {
@@ -131,13 +315,42 @@ impl NegentropyStorageVector {
     }
 }
 
-impl NegentropyStorageBase for NegentropyStorageVector {
+/// Collect into a sealed storage
+///
+/// Items are sorted and deduplicated only if not already sorted.
+impl<const N: usize> FromIterator<Item<N>> for NegentropyStorageVector<N> {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = Item<N>>,
+    {
+        let mut items: Vec<Item<N>> = iter.into_iter().collect();
+
+        if !is_strictly_sorted(&items) {
+            items.sort();
+            items.dedup();
+        }
+
+        Self::from_sorted_unchecked(items)
+    }
+}
+
+/// Add items: see [`NegentropyStorageVector::insert_batch`]
+impl<const N: usize> Extend<Item<N>> for NegentropyStorageVector<N> {
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = Item<N>>,
+    {
+        self.insert_batch(iter);
+    }
+}
+
+impl<const N: usize> NegentropyStorageBase<N> for NegentropyStorageVector<N> {
     fn size(&self) -> Result<usize, Error> {
         self.check_sealed()?;
         Ok(self.items.len())
     }
 
-    fn get_item(&self, i: usize) -> Result<Option<Item>, Error> {
+    fn get_item(&self, i: usize) -> Result<Option<Item<N>>, Error> {
         self.check_sealed()?;
         Ok(self.items.get(i).copied())
     }
@@ -146,7 +359,7 @@ impl NegentropyStorageBase for NegentropyStorageVector {
         &self,
         begin: usize,
         end: usize,
-        cb: &mut dyn FnMut(Item, usize) -> Result<bool, Error>,
+        cb: &mut dyn FnMut(Item<N>, usize) -> Result<bool, Error>,
     ) -> Result<(), Error> {
         self.check_sealed()?;
         self.check_bounds(begin, end)?;
@@ -160,7 +373,7 @@ impl NegentropyStorageBase for NegentropyStorageVector {
         Ok(())
     }
 
-    fn find_lower_bound(&self, mut first: usize, last: usize, value: &Bound) -> usize {
+    fn find_lower_bound(&self, mut first: usize, last: usize, value: &Bound<N>) -> usize {
         let mut count: usize = last - first;
 
         while count > 0 {
@@ -180,3 +393,178 @@ impl NegentropyStorageBase for NegentropyStorageVector {
         first
     }
 }
+
+/// Validated: a sealed storage fails with [`Error::UnsortedItems`] if its items are not sorted or not unique
+#[cfg(feature = "serde")]
+impl<'de, const N: usize> serde::Deserialize<'de> for NegentropyStorageVector<N> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(serde::Deserialize)]
+        #[serde(rename = "NegentropyStorageVector")]
+        struct Raw<const N: usize> {
+            items: Vec<Item<N>>,
+            sealed: bool,
+        }
+
+        let Raw { items, sealed } = Raw::deserialize(deserializer)?;
+        if sealed {
+            Self::from_sorted(items).map_err(serde::de::Error::custom)
+        } else {
+            Ok(Self { items, sealed })
+        }
+    }
+}
+
+#[inline]
+fn is_strictly_sorted<const N: usize>(items: &[Item<N>]) -> bool {
+    items.windows(2).all(|w| w[0] < w[1])
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "serde")]
+    use alloc::string::ToString;
+    use alloc::vec;
+
+    use super::*;
+
+    fn item(timestamp: u64, id: u8) -> Item {
+        Item::with_timestamp_and_id(timestamp, Id::from_byte_array([id; 32]))
+    }
+
+    #[test]
+    fn test_from_sorted() {
+        let items = vec![item(0, 1), item(0, 2), item(1, 0)];
+        let storage = NegentropyStorageVector::from_sorted(items.clone()).unwrap();
+        assert_eq!(storage.size().unwrap(), 3);
+        assert_eq!(storage.get_item(2).unwrap(), Some(item(1, 0)));
+        assert_eq!(
+            storage,
+            NegentropyStorageVector::from_sorted_unchecked(items)
+        );
+
+        assert_eq!(
+            NegentropyStorageVector::from_sorted(vec![item(1, 0), item(0, 1)]),
+            Err(Error::UnsortedItems)
+        );
+        assert_eq!(
+            NegentropyStorageVector::from_sorted(vec![item(0, 1), item(0, 1)]),
+            Err(Error::UnsortedItems)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde() {
+        let sealed: NegentropyStorageVector = vec![item(1, 0), item(0, 1)].into_iter().collect();
+        let json = serde_json::to_string(&sealed).unwrap();
+        assert_eq!(
+            serde_json::from_str::<NegentropyStorageVector>(&json).unwrap(),
+            sealed
+        );
+
+        // Unsealed storages are kept as they are
+        let mut unsealed = NegentropyStorageVector::new();
+        unsealed.insert(1, Id::from_byte_array([0; 32])).unwrap();
+        unsealed.insert(1, Id::from_byte_array([0; 32])).unwrap();
+        let json = serde_json::to_string(&unsealed).unwrap();
+        assert_eq!(
+            serde_json::from_str::<NegentropyStorageVector>(&json).unwrap(),
+            unsealed
+        );
+
+        for items in [vec![item(1, 0), item(0, 1)], vec![item(0, 1), item(0, 1)]] {
+            let json = serde_json::to_string(&NegentropyStorageVector {
+                items,
+                sealed: true,
+            })
+            .unwrap();
+            assert!(serde_json::from_str::<NegentropyStorageVector>(&json)
+                .unwrap_err()
+                .to_string()
+                .starts_with("items not sorted or not unique"));
+        }
+    }
+
+    #[test]
+    fn test_from_iter_and_extend() {
+        let mut sealed = NegentropyStorageVector::new();
+        for (timestamp, id) in [(2, 0), (0, 1), (1, 0), (0, 1)] {
+            sealed
+                .insert(timestamp, Id::from_byte_array([id; 32]))
+                .unwrap();
+        }
+        sealed.seal().unwrap();
+
+        let storage: NegentropyStorageVector = vec![item(2, 0), item(0, 1), item(1, 0), item(0, 1)]
+            .into_iter()
+            .collect();
+        assert_eq!(storage, sealed);
+
+        let mut storage: NegentropyStorageVector = vec![item(0, 1)].into_iter().collect();
+        storage.extend(vec![item(1, 0), item(2, 0)]);
+        assert_eq!(storage, sealed);
+        storage.extend(vec![item(0, 1)]);
+        assert_eq!(storage, sealed);
+
+        // Not sealed: items are only added
+        let mut storage = NegentropyStorageVector::new();
+        storage.extend(vec![item(2, 0), item(0, 1)]);
+        assert_eq!(storage.size(), Err(Error::NotSealed));
+        storage.seal().unwrap();
+        assert_eq!(storage.get_item(0).unwrap(), Some(item(0, 1)));
+    }
+
+    #[test]
+    fn test_insert_batch() {
+        let mut storage: NegentropyStorageVector =
+            (0..100u8).step_by(2).map(|i| item(i as u64, i)).collect();
+
+        // Merge, with duplicates
+        storage.insert_batch((0..100u8).rev().step_by(3).map(|i| item(i as u64, i)));
+        storage.insert_batch(vec![item(99, 99), item(4, 4)]);
+
+        let mut expected = NegentropyStorageVector::new();
+        for i in (0..100u8).filter(|i| i % 2 == 0 || i % 3 == 0) {
+            expected
+                .insert(i as u64, Id::from_byte_array([i; 32]))
+                .unwrap();
+        }
+        expected.seal().unwrap();
+        assert_eq!(storage, expected);
+
+        // Append
+        storage.insert_batch(vec![item(200, 0), item(100, 0)]);
+        assert_eq!(storage.size().unwrap(), expected.size().unwrap() + 2);
+        assert_eq!(
+            storage.get_item(storage.size().unwrap() - 1).unwrap(),
+            Some(item(200, 0))
+        );
+    }
+
+    #[test]
+    fn test_remove_and_retain() {
+        let mut storage: NegentropyStorageVector = (0..10u8).map(|i| item(i as u64, i)).collect();
+
+        assert!(storage.remove(3, Id::from_byte_array([3; 32])));
+        assert!(!storage.remove(3, Id::from_byte_array([3; 32])));
+        assert!(!storage.remove(4, Id::from_byte_array([5; 32])));
+        assert_eq!(storage.size().unwrap(), 9);
+
+        storage.retain(|item| item.timestamp >= 5);
+        assert_eq!(
+            storage,
+            NegentropyStorageVector::from_sorted((5..10u8).map(|i| item(i as u64, i)).collect())
+                .unwrap()
+        );
+
+        let mut storage = NegentropyStorageVector::new();
+        storage.insert(0, Id::from_byte_array([0; 32])).unwrap();
+        storage.insert(0, Id::from_byte_array([0; 32])).unwrap();
+        assert!(storage.remove(0, Id::from_byte_array([0; 32])));
+        storage.seal().unwrap();
+        assert_eq!(storage.size().unwrap(), 0);
+    }
+}
@@ -3,13 +3,12 @@
 // Distributed under the MIT software license
 
 use alloc::vec::Vec;
-use core::cmp::Ordering;
+use core::cmp::{self, Ordering};
 use core::convert::{TryFrom, TryInto};
-use core::num::Wrapping;
 use core::ops::Deref;
 
 use crate::encoding::encode_var_int;
-use crate::{sha256, Error, Id, FINGERPRINT_SIZE, ID_SIZE};
+use crate::{Error, FingerprintHasher, Id, FINGERPRINT_SIZE, ID_SIZE, MAX_ID_SIZE};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
@@ -17,12 +16,21 @@ pub(crate) enum Mode {
     Skip = 0,
     Fingerprint = 1,
     IdList = 2,
+    #[cfg(feature = "experimental-id-prefix")]
+    IdPrefixList = 3,
+    #[cfg(feature = "experimental-id-prefix")]
+    IdPrefixDiff = 4,
 }
 
 impl Mode {
     pub fn as_u64(&self) -> u64 {
         *self as u64
     }
+
+    #[cfg(feature = "experimental-id-prefix")]
+    pub fn is_id_prefix(&self) -> bool {
+        matches!(self, Mode::IdPrefixList | Mode::IdPrefixDiff)
+    }
 }
 
 impl TryFrom<u64> for Mode {
@@ -32,6 +40,10 @@ impl TryFrom<u64> for Mode {
             0 => Ok(Mode::Skip),
             1 => Ok(Mode::Fingerprint),
             2 => Ok(Mode::IdList),
+            #[cfg(feature = "experimental-id-prefix")]
+            3 => Ok(Mode::IdPrefixList),
+            #[cfg(feature = "experimental-id-prefix")]
+            4 => Ok(Mode::IdPrefixDiff),
             m => Err(Error::UnexpectedMode(m)),
         }
     }
@@ -39,20 +51,21 @@ impl TryFrom<u64> for Mode {
 
 /// Item
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
-pub struct Item {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Item<const N: usize = ID_SIZE> {
     /// timestamp
     pub timestamp: u64,
     /// Id
-    pub id: Id,
+    pub id: Id<N>,
 }
 
-impl PartialOrd for Item {
+impl<const N: usize> PartialOrd for Item<N> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl Ord for Item {
+impl<const N: usize> Ord for Item<N> {
     fn cmp(&self, other: &Self) -> Ordering {
         if self.timestamp != other.timestamp {
             self.timestamp.cmp(&other.timestamp)
@@ -62,7 +75,7 @@ impl Ord for Item {
     }
 }
 
-impl Item {
+impl<const N: usize> Item<N> {
     /// new Item
     pub fn new() -> Self {
         Self::default()
@@ -77,26 +90,27 @@ impl Item {
 
     /// new Item with timestamp and id
     #[inline]
-    pub fn with_timestamp_and_id(timestamp: u64, id: Id) -> Self {
+    pub fn with_timestamp_and_id(timestamp: u64, id: Id<N>) -> Self {
         Self { timestamp, id }
     }
 
     /// get id
-    pub fn get_id(&self) -> &Id {
+    pub fn get_id(&self) -> &Id<N> {
         &self.id
     }
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 /// Bound
-pub struct Bound {
+pub struct Bound<const N: usize = ID_SIZE> {
     /// Item
-    pub item: Item,
+    pub item: Item<N>,
     /// ID Len
     pub id_len: usize,
 }
 
-impl Bound {
+impl<const N: usize> Bound<N> {
     /// New Bound
     #[inline]
     pub fn new() -> Self {
@@ -104,10 +118,10 @@ impl Bound {
     }
 
     /// new Bound from item
-    pub fn from_item(item: &Item) -> Self {
+    pub fn from_item(item: &Item<N>) -> Self {
         let mut bound = Self::new();
         bound.item = *item;
-        bound.id_len = ID_SIZE;
+        bound.id_len = N;
         bound
     }
 
@@ -127,7 +141,7 @@ impl Bound {
         let id: &[u8] = id.as_ref();
         let len: usize = id.len();
 
-        if len > ID_SIZE {
+        if len > N {
             return Err(Error::IdTooBig);
         }
 
@@ -140,18 +154,43 @@ impl Bound {
     }
 }
 
-impl PartialOrd for Bound {
+impl<const N: usize> PartialOrd for Bound<N> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl Ord for Bound {
+impl<const N: usize> Ord for Bound<N> {
     fn cmp(&self, other: &Self) -> Ordering {
         self.item.cmp(&other.item)
     }
 }
 
+/// Validated: fails if `id_len` is greater than `N`, and the ID bytes past `id_len` are zeroed
+#[cfg(feature = "serde")]
+impl<'de, const N: usize> serde::Deserialize<'de> for Bound<N> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(serde::Deserialize)]
+        #[serde(rename = "Bound")]
+        struct Raw<const N: usize> {
+            item: Item<N>,
+            id_len: usize,
+        }
+
+        let raw: Raw<N> = Raw::deserialize(deserializer)?;
+        let id: &[u8] = raw
+            .item
+            .id
+            .as_bytes()
+            .get(..raw.id_len)
+            .ok_or_else(|| serde::de::Error::custom(Error::IdTooBig))?;
+        Bound::with_timestamp_and_id(raw.item.timestamp, id).map_err(serde::de::Error::custom)
+    }
+}
+
 /// Fingerprint
 #[derive(Debug, Clone, Copy, Default)]
 pub struct Fingerprint {
@@ -167,6 +206,13 @@ impl Deref for Fingerprint {
 }
 
 impl Fingerprint {
+    /// Construct from bytes
+    #[inline]
+    pub fn from_bytes(buf: [u8; FINGERPRINT_SIZE]) -> Self {
+        Self { buf }
+    }
+
+    /// Return the inner value
     #[inline]
     pub fn to_bytes(self) -> [u8; FINGERPRINT_SIZE] {
         self.buf
@@ -174,104 +220,289 @@ impl Fingerprint {
 }
 
 /// Accumulator
-#[derive(Debug, Clone, Copy, Default)]
-pub struct Accumulator {
-    buf: [u8; ID_SIZE],
+///
+/// `N`-byte little-endian integer: IDs are added modulo `2^(8 * N)`.
+/// Stored as 64-bit limbs (least significant first), `N` must be at most [`MAX_ID_SIZE`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Accumulator<const N: usize = ID_SIZE> {
+    /// Only the first `(N + 7) / 8` limbs are used, the others are always 0
+    limbs: [u64; MAX_LIMBS],
 }
 
-impl Accumulator {
-    /// New Accumulator
-    pub fn new() -> Self {
-        Self { buf: [0; ID_SIZE] }
-    }
-
-    /* /// Add Item
-    pub fn add_item(&mut self, item: &Item) {
-        self.add(&item.id);
+impl<const N: usize> Default for Accumulator<N> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
     }
+}
 
-    /// Add Accum
-    pub fn add_accum(&mut self, accum: &Accumulator) {
-        self.add(&accum.buf);
-    } */
-
-    /// Add
-    pub fn add(&mut self, buf: &[u8; ID_SIZE]) -> Result<(), Error> {
-        let mut curr_carry = Wrapping(0u64);
-        let mut next_carry = Wrapping(0u64);
-
-        let p = &self.buf[..];
-        let po = buf;
-
-        let mut wtr = Vec::with_capacity(ID_SIZE);
-
-        for i in 0..4 {
-            let orig = Wrapping(u64::from_le_bytes(p[(i * 8)..(i * 8 + 8)].try_into()?));
-            let other_v = Wrapping(u64::from_le_bytes(po[(i * 8)..(i * 8 + 8)].try_into()?));
+impl<const N: usize> Accumulator<N> {
+    const LIMBS: usize = (N + 7) / 8;
 
-            let mut next = orig;
+    /// Mask of the most significant limb, which is partial if `N` is not a multiple of 8
+    const TOP_MASK: u64 = if N % 8 == 0 {
+        u64::MAX
+    } else {
+        (1 << ((N % 8) * 8)) - 1
+    };
 
-            next += curr_carry;
-            if next < orig {
-                next_carry = Wrapping(1u64);
-            }
+    const SIZE_CHECK: () = assert!(N <= MAX_ID_SIZE, "ID size not supported by the accumulator");
 
-            next += other_v;
-            if next < other_v {
-                next_carry = Wrapping(1u64);
-            }
+    /// New Accumulator
+    #[inline]
+    pub fn new() -> Self {
+        #[allow(clippy::let_unit_value)]
+        let _check: () = Self::SIZE_CHECK;
 
-            wtr.extend_from_slice(&next.0.to_le_bytes());
-            curr_carry = next_carry;
-            next_carry = Wrapping(0u64);
+        Self {
+            limbs: [0; MAX_LIMBS],
         }
+    }
 
-        self.buf.copy_from_slice(&wtr);
+    /// Add ID (mod 2^(8 * N))
+    #[inline]
+    pub fn add(&mut self, buf: &[u8; N]) {
+        let mut carry: bool = false;
+
+        for (i, limb) in self.limbs[..Self::LIMBS].iter_mut().enumerate() {
+            let (sum, c1) = limb.overflowing_add(read_limb(buf, i));
+            let (sum, c2) = sum.overflowing_add(carry as u64);
+            *limb = sum;
+            carry = c1 | c2;
+        }
 
-        Ok(())
+        self.truncate();
     }
 
-    /* /// Negate
-    pub fn negate(&mut self) -> () {
-        for i in 0..ID_SIZE {
-            self.buf[i] = !self.buf[i];
+    /// Subtract ID (mod 2^(8 * N))
+    ///
+    /// Inverse of [`Accumulator::add`]: the sum of a range can be computed from two prefix sums.
+    #[inline]
+    pub fn sub(&mut self, buf: &[u8; N]) {
+        let mut borrow: bool = false;
+
+        for (i, limb) in self.limbs[..Self::LIMBS].iter_mut().enumerate() {
+            let (diff, b1) = limb.overflowing_sub(read_limb(buf, i));
+            let (diff, b2) = diff.overflowing_sub(borrow as u64);
+            *limb = diff;
+            borrow = b1 | b2;
         }
 
-        let mut one = Accumulator::new();
-        one.buf[0] = 1u8;
-        self.add(&one.buf);
+        self.truncate();
     }
 
-    /// Sub Item
-    pub fn sub_item(&mut self, item: &Item) {
-        self.sub(&item.id);
+    /// Drop the bits past `8 * N` (mod 2^(8 * N))
+    #[inline(always)]
+    fn truncate(&mut self) {
+        if let Some(top) = self.limbs[..Self::LIMBS].last_mut() {
+            *top &= Self::TOP_MASK;
+        }
     }
 
-    /// Sub Accum
-    pub fn sub_accum(&mut self, accum: &Accumulator) {
-        self.sub(&accum.buf);
+    /// Deserialize from little-endian bytes
+    #[inline]
+    pub fn from_bytes(buf: [u8; N]) -> Self {
+        let mut acc: Self = Self::new();
+        for (i, limb) in acc.limbs[..Self::LIMBS].iter_mut().enumerate() {
+            *limb = read_limb(&buf, i);
+        }
+        acc
     }
 
-    /// Sub
-    pub fn sub(&mut self, buf: &[u8; ID_SIZE]) -> () {
-        let mut neg = Accumulator::new();
-        neg.buf = *buf;
-        neg.negate();
-        self.add_accum(&neg);
-    } */
+    /// Serialize to little-endian bytes
+    #[inline]
+    pub fn to_bytes(self) -> [u8; N] {
+        let mut buf: [u8; N] = [0u8; N];
+        for (chunk, limb) in buf.chunks_mut(8).zip(self.limbs.iter()) {
+            chunk.copy_from_slice(&limb.to_le_bytes()[..chunk.len()]);
+        }
+        buf
+    }
 
     /// Compute fingerprint, given set size
-    pub fn get_fingerprint(&self, n: u64) -> Result<Fingerprint, Error> {
+    pub fn get_fingerprint<H>(&self, hasher: &H, n: u64) -> Result<Fingerprint, Error>
+    where
+        H: FingerprintHasher + ?Sized,
+    {
         let var_int: Vec<u8> = encode_var_int(n);
 
-        let mut input: Vec<u8> = Vec::with_capacity(ID_SIZE + var_int.len());
-        input.extend(&self.buf);
+        let mut input: Vec<u8> = Vec::with_capacity(N + var_int.len());
+        input.extend(&self.to_bytes());
         input.extend(var_int);
 
-        let hash: [u8; 32] = sha256::hash(input);
+        let hash: [u8; 32] = hasher.hash(&input);
 
         Ok(Fingerprint {
             buf: hash[0..FINGERPRINT_SIZE].try_into()?,
         })
     }
 }
+
+const MAX_LIMBS: usize = MAX_ID_SIZE / 8;
+
+/// Read the `i`-th little-endian 64-bit limb, zero-padded past the end of `bytes`
+#[inline(always)]
+fn read_limb(bytes: &[u8], i: usize) -> u64 {
+    let start: usize = i * 8;
+    let end: usize = cmp::min(start + 8, bytes.len());
+    let mut word: [u8; 8] = [0u8; 8];
+    word[..end - start].copy_from_slice(&bytes[start..end]);
+    u64::from_le_bytes(word)
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "serde")]
+    use alloc::format;
+    #[cfg(feature = "serde")]
+    use alloc::string::{String, ToString};
+
+    use super::*;
+    use crate::Sha256;
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde() {
+        let item = Item::with_timestamp_and_id(42, Id::from_byte_array([0xab; ID_SIZE]));
+        let json: String = serde_json::to_string(&item).unwrap();
+        assert_eq!(
+            json,
+            format!("{{\"timestamp\":42,\"id\":\"{}\"}}", "ab".repeat(ID_SIZE))
+        );
+        assert_eq!(serde_json::from_str::<Item>(&json).unwrap(), item);
+
+        let bound: Bound = Bound::with_timestamp_and_id(42, [0xab; 4]).unwrap();
+        let json: String = serde_json::to_string(&bound).unwrap();
+        assert_eq!(serde_json::from_str::<Bound>(&json).unwrap(), bound);
+
+        // Bytes past `id_len` are dropped
+        let json: String = serde_json::to_string(&Bound { item, id_len: 4 }).unwrap();
+        assert_eq!(serde_json::from_str::<Bound>(&json).unwrap(), bound);
+
+        let json: String = serde_json::to_string(&Bound {
+            item,
+            id_len: ID_SIZE + 1,
+        })
+        .unwrap();
+        assert!(serde_json::from_str::<Bound>(&json)
+            .unwrap_err()
+            .to_string()
+            .starts_with("ID too big"));
+    }
+
+    #[test]
+    fn test_accumulator_add() {
+        let mut acc = Accumulator::new();
+        acc.add(&[0x01; ID_SIZE]);
+        acc.add(&[0x02; ID_SIZE]);
+        assert_eq!(acc.to_bytes(), [0x03; ID_SIZE]);
+    }
+
+    #[test]
+    fn test_accumulator_carry() {
+        // Carry must propagate across word boundaries
+        let mut acc = Accumulator::new();
+        let mut a: [u8; ID_SIZE] = [0u8; ID_SIZE];
+        a[..8].copy_from_slice(&[0xff; 8]);
+        acc.add(&a);
+
+        let mut one: [u8; ID_SIZE] = [0u8; ID_SIZE];
+        one[0] = 1;
+        acc.add(&one);
+
+        let mut expected: [u8; ID_SIZE] = [0u8; ID_SIZE];
+        expected[8] = 1;
+        assert_eq!(acc.to_bytes(), expected);
+
+        // Overflow of the most significant word wraps around (mod 2^256)
+        let mut acc = Accumulator::new();
+        acc.add(&[0xff; ID_SIZE]);
+        acc.add(&one);
+        assert_eq!(acc.to_bytes(), [0u8; ID_SIZE]);
+    }
+
+    #[test]
+    fn test_accumulator_carry_non_word_size() {
+        // 20-byte IDs: carry must propagate from the last word into the byte-wise tail
+        let mut acc: Accumulator<20> = Accumulator::new();
+        let mut a: [u8; 20] = [0u8; 20];
+        a[..16].copy_from_slice(&[0xff; 16]);
+        acc.add(&a);
+
+        let mut one: [u8; 20] = [0u8; 20];
+        one[0] = 1;
+        acc.add(&one);
+
+        let mut expected: [u8; 20] = [0u8; 20];
+        expected[16] = 1;
+        assert_eq!(acc.to_bytes(), expected);
+
+        // Wraps around (mod 2^160)
+        let mut acc: Accumulator<20> = Accumulator::new();
+        acc.add(&[0xff; 20]);
+        acc.add(&one);
+        assert_eq!(acc.to_bytes(), [0u8; 20]);
+    }
+
+    #[test]
+    fn test_accumulator_bytes() {
+        let mut buf: [u8; 20] = [0u8; 20];
+        for (i, byte) in buf.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        assert_eq!(Accumulator::<20>::from_bytes(buf).to_bytes(), buf);
+
+        // Largest supported size
+        let mut acc: Accumulator<MAX_ID_SIZE> = Accumulator::from_bytes([0xff; MAX_ID_SIZE]);
+        let mut one: [u8; MAX_ID_SIZE] = [0u8; MAX_ID_SIZE];
+        one[0] = 1;
+        acc.add(&one);
+        assert_eq!(acc, Accumulator::new());
+    }
+
+    #[test]
+    fn test_accumulator_sub() {
+        let mut one: [u8; 20] = [0u8; 20];
+        one[0] = 1;
+
+        // Borrow across the word boundary and into the byte-wise tail, wrapping around
+        let mut acc: Accumulator<20> = Accumulator::new();
+        acc.sub(&one);
+        assert_eq!(acc.to_bytes(), [0xff; 20]);
+
+        let mut acc: Accumulator = Accumulator::new();
+        acc.add(&[0x17; ID_SIZE]);
+        acc.add(&[0xf3; ID_SIZE]);
+        acc.sub(&[0x17; ID_SIZE]);
+        assert_eq!(acc.to_bytes(), [0xf3; ID_SIZE]);
+    }
+
+    #[test]
+    fn test_accumulator_fingerprint() {
+        // Fingerprint of the empty set: sha256(zero accumulator || varint(0))[..16]
+        let acc: Accumulator = Accumulator::new();
+        let fingerprint = acc.get_fingerprint(&Sha256, 0).unwrap();
+        assert_eq!(
+            fingerprint.to_bytes(),
+            [
+                0x7f, 0x9c, 0x9e, 0x31, 0xac, 0x82, 0x56, 0xca, 0x2f, 0x25, 0x85, 0x83, 0xdf, 0x26,
+                0x2d, 0xbc
+            ]
+        );
+    }
+}
+
+#[cfg(bench)]
+mod benches {
+    use super::*;
+    use crate::test::{black_box, Bencher};
+
+    #[bench]
+    pub fn accumulator_add(bh: &mut Bencher) {
+        let mut acc = Accumulator::new();
+        let id: [u8; ID_SIZE] = [0xaa; ID_SIZE];
+        bh.iter(|| {
+            acc.add(black_box(&id));
+        });
+    }
+}
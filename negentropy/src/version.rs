@@ -0,0 +1,170 @@
+// Copyright (c) 2023 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! Supported-version mask for the protocol version negotiation
+
+use core::fmt;
+
+#[cfg(feature = "experimental-id-prefix")]
+use crate::constants::ID_PREFIX_PROTOCOL_VERSION;
+use crate::constants::PROTOCOL_VERSION;
+use crate::Error;
+
+const FIRST_VERSION: u64 = 0x60;
+const LAST_VERSION: u64 = 0x6F;
+
+/// Protocol versions implemented by this crate
+const IMPLEMENTED: &[u64] = &[
+    PROTOCOL_VERSION,
+    #[cfg(feature = "experimental-id-prefix")]
+    ID_PREFIX_PROTOCOL_VERSION,
+];
+
+/// Mask of the protocol versions supported by a [`Negentropy`](crate::Negentropy) instance
+///
+/// Only the versions implemented by this crate can be enabled (see [`SupportedVersions::implemented`]):
+/// the mask selects which of them are offered and accepted, it doesn't register new protocol versions.
+///
+/// The initiator proposes its highest version. A non-initiator that doesn't support it
+/// replies with its own highest version, and the initiator retries with the highest version
+/// it supports that is not greater than that one (and lower than the rejected one).
+///
+/// Defaults to [`PROTOCOL_VERSION`] only.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct SupportedVersions {
+    /// Bit `i` set if version `0x60 + i` is supported
+    mask: u16,
+}
+
+impl Default for SupportedVersions {
+    fn default() -> Self {
+        Self {
+            mask: Self::bit(PROTOCOL_VERSION),
+        }
+    }
+}
+
+impl fmt::Debug for SupportedVersions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl SupportedVersions {
+    /// Empty set
+    #[inline]
+    pub const fn empty() -> Self {
+        Self { mask: 0 }
+    }
+
+    /// All the versions implemented by this crate
+    pub fn implemented() -> Self {
+        let mut versions = Self::empty();
+        for version in IMPLEMENTED.iter() {
+            versions.mask |= Self::bit(*version);
+        }
+        versions
+    }
+
+    /// Check if `version` is implemented by this crate
+    #[inline]
+    pub fn is_implemented(version: u64) -> bool {
+        IMPLEMENTED.contains(&version)
+    }
+
+    /// Enable a version
+    ///
+    /// Fails with [`Error::UnsupportedProtocolVersion`] if the version is not implemented by this crate.
+    pub fn insert(&mut self, version: u64) -> Result<(), Error> {
+        if !Self::is_implemented(version) {
+            return Err(Error::UnsupportedProtocolVersion);
+        }
+
+        self.mask |= Self::bit(version);
+
+        Ok(())
+    }
+
+    /// Disable a version
+    #[inline]
+    pub fn remove(&mut self, version: u64) {
+        if Self::in_range(version) {
+            self.mask &= !Self::bit(version);
+        }
+    }
+
+    /// Check if `version` is in the set
+    #[inline]
+    pub fn contains(&self, version: u64) -> bool {
+        Self::in_range(version) && self.mask & Self::bit(version) != 0
+    }
+
+    /// Check if the set is empty
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.mask == 0
+    }
+
+    /// Highest version in the set
+    #[inline]
+    pub fn highest(&self) -> Option<u64> {
+        self.iter().next_back()
+    }
+
+    /// Highest version in the set that is lower than `version`
+    #[inline]
+    pub fn highest_below(&self, version: u64) -> Option<u64> {
+        self.iter().rev().find(|v| *v < version)
+    }
+
+    /// Iterate versions in ascending order
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = u64> {
+        let mask: u16 = self.mask;
+        (FIRST_VERSION..=LAST_VERSION).filter(move |v| mask & Self::bit(*v) != 0)
+    }
+
+    #[inline]
+    fn in_range(version: u64) -> bool {
+        (FIRST_VERSION..=LAST_VERSION).contains(&version)
+    }
+
+    #[inline]
+    const fn bit(version: u64) -> u16 {
+        1 << (version - FIRST_VERSION)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    #[test]
+    fn test_supported_versions() {
+        let mut versions = SupportedVersions::default();
+        assert!(versions.contains(PROTOCOL_VERSION));
+        assert_eq!(versions.highest(), Some(PROTOCOL_VERSION));
+        assert_eq!(versions.highest_below(PROTOCOL_VERSION), None);
+        assert_eq!(versions.highest_below(0x6F), Some(PROTOCOL_VERSION));
+
+        assert_eq!(
+            versions.insert(0x62),
+            Err(Error::UnsupportedProtocolVersion)
+        );
+        assert_eq!(
+            versions.insert(0x70),
+            Err(Error::UnsupportedProtocolVersion)
+        );
+        assert!(!versions.contains(0x70));
+
+        versions.remove(PROTOCOL_VERSION);
+        assert!(versions.is_empty());
+        assert_eq!(versions.highest(), None);
+
+        assert_eq!(
+            SupportedVersions::implemented().iter().collect::<Vec<_>>(),
+            IMPLEMENTED
+        );
+    }
+}